@@ -0,0 +1,603 @@
+use std::fmt;
+
+use super::{
+    Dst, ExecutionPlane, Instruction, NodeKind, Port, Register, Src, TruePort,
+    INSTRUCTIONS_PER_NODE, MAX_VALUE, MIN_VALUE, NODES_PER_PLANE,
+};
+
+// a stable binary form for programs, one little endian u32 per instruction
+//
+//   31..28  opcode
+//   27..24  source tag
+//   23..20  destination tag
+//   19..11  reserved, always 0
+//   10..0   literal (two's complement) or jump target
+//
+// a plane is MAGIC, VERSION and a node count, then a kind byte per node (0 compute,
+// 1 stack, 2 damaged), then per node an instruction count, a u32 mask of breakpoints and
+// the instructions themselves. version 1 had no kind bytes and is read as all compute
+
+pub const MAGIC: [u8; 4] = *b"T21B";
+pub const VERSION: u8 = 2;
+
+// the order here is the encoding, never reorder, only append
+static KINDS: [NodeKind; 3] = [NodeKind::Compute, NodeKind::Stack, NodeKind::Damaged];
+
+const LITERAL_BITS: u32 = 11;
+const LITERAL_MASK: u32 = (1 << LITERAL_BITS) - 1;
+const RESERVED_MASK: u32 = 0x000f_f800;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    // ran out of bytes partway through
+    Truncated,
+    TrailingBytes(usize),
+    NodeCount(u8),
+    InvalidNodeKind(u8),
+    // a stack or damaged node with instructions
+    NoCode(u8),
+    TooManyInstructions { node: u8, count: u8 },
+    InvalidOpcode(u32),
+    InvalidTag(u32),
+    ReservedBits(u32),
+    LiteralOutOfRange(i16),
+    InvalidJump(u32),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BadMagic => write!(f, "not a bytecode file"),
+            Self::UnsupportedVersion(v) => {
+                write!(f, "bytecode version {} is not supported ({})", v, VERSION)
+            }
+            Self::Truncated => write!(f, "bytecode ends too early"),
+            Self::TrailingBytes(n) => write!(f, "{} bytes left over after the last node", n),
+            Self::NodeCount(n) => write!(f, "{} nodes, expected {}", n, NODES_PER_PLANE),
+            Self::InvalidNodeKind(k) => write!(f, "invalid node kind {}", k),
+            Self::NoCode(n) => write!(f, "node {} holds no code but has instructions", n),
+            Self::TooManyInstructions { node, count } => write!(
+                f,
+                "node {} has {} instructions ({} max)",
+                node, count, INSTRUCTIONS_PER_NODE
+            ),
+            Self::InvalidOpcode(w) => write!(f, "invalid opcode in {:#010x}", w),
+            Self::InvalidTag(w) => write!(f, "invalid operand tag in {:#010x}", w),
+            Self::ReservedBits(w) => write!(f, "reserved bits set in {:#010x}", w),
+            Self::LiteralOutOfRange(v) => {
+                write!(
+                    f,
+                    "literal {} is outside of {}..={}",
+                    v, MIN_VALUE, MAX_VALUE
+                )
+            }
+            Self::InvalidJump(w) => write!(f, "jump target out of range in {:#010x}", w),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// instructions built by hand can hold what no source or bytecode can express
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EncodeError {
+    LiteralOutOfRange(i16),
+    InvalidJump(u8),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::LiteralOutOfRange(v) => write!(
+                f,
+                "literal {} is outside of {}..={}",
+                v, MIN_VALUE, MAX_VALUE
+            ),
+            Self::InvalidJump(t) => write!(
+                f,
+                "jump target {} is past the last slot ({} max)",
+                t,
+                INSTRUCTIONS_PER_NODE - 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+// the order here is the encoding, never reorder, only append
+static OPCODES: [&str; 13] = [
+    "MOV", "ADD", "SUB", "JRO", "JMP", "JEZ", "JNZ", "JGZ", "JLZ", "SAV", "SWP", "NEG", "HCF",
+];
+
+const TAG_NONE: u32 = 0;
+const TAG_LITERAL: u32 = 1;
+// registers and ports, from 2 on
+static TAGS: [Src; 8] = [
+    Src::Register(Register::Acc),
+    Src::Register(Register::Nil),
+    Src::Port(Port::True(TruePort::Up)),
+    Src::Port(Port::True(TruePort::Down)),
+    Src::Port(Port::True(TruePort::Left)),
+    Src::Port(Port::True(TruePort::Right)),
+    Src::Port(Port::True(TruePort::Any)),
+    Src::Port(Port::Last),
+];
+
+fn opcode(instruction: &Instruction) -> u32 {
+    (match instruction {
+        Instruction::Mov(..) => 0,
+        Instruction::Add(_) => 1,
+        Instruction::Sub(_) => 2,
+        Instruction::Jro(_) => 3,
+        Instruction::Jmp(_) => 4,
+        Instruction::Jez(_) => 5,
+        Instruction::Jnz(_) => 6,
+        Instruction::Jgz(_) => 7,
+        Instruction::Jlz(_) => 8,
+        Instruction::Sav => 9,
+        Instruction::Swp => 10,
+        Instruction::Neg => 11,
+        Instruction::Hcf => 12,
+    }) << 28
+}
+
+// tag and literal field of a source operand
+fn encode_src(src: Src) -> Result<(u32, u32), EncodeError> {
+    match src {
+        Src::Literal(value) if !(MIN_VALUE..=MAX_VALUE).contains(&value) => {
+            Err(EncodeError::LiteralOutOfRange(value))
+        }
+        Src::Literal(value) => Ok((TAG_LITERAL, value as u32 & LITERAL_MASK)),
+        operand => Ok((
+            TAGS.iter().position(|t| *t == operand).unwrap() as u32 + 2,
+            0,
+        )),
+    }
+}
+
+fn encode_dst(dst: Dst) -> u32 {
+    let tag = match dst {
+        Dst::Register(r) => encode_src(Src::Register(r)),
+        Dst::Port(p) => encode_src(Src::Port(p)),
+    };
+    tag.expect("registers and ports always encode").0
+}
+
+// anything encoded here decodes back to the same instruction
+pub fn encode_instruction(instruction: Instruction) -> Result<u32, EncodeError> {
+    let operands = match instruction {
+        Instruction::Mov(src, dst) => {
+            let (tag, literal) = encode_src(src)?;
+            tag << 24 | encode_dst(dst) << 20 | literal
+        }
+        Instruction::Add(src) | Instruction::Sub(src) | Instruction::Jro(src) => {
+            let (tag, literal) = encode_src(src)?;
+            tag << 24 | literal
+        }
+        Instruction::Jmp(t)
+        | Instruction::Jez(t)
+        | Instruction::Jnz(t)
+        | Instruction::Jgz(t)
+        | Instruction::Jlz(t) => {
+            if t as usize >= INSTRUCTIONS_PER_NODE {
+                return Err(EncodeError::InvalidJump(t));
+            }
+            t as u32
+        }
+        Instruction::Sav | Instruction::Swp | Instruction::Neg | Instruction::Hcf => 0,
+    };
+    Ok(opcode(&instruction) | operands)
+}
+
+pub fn decode_instruction(word: u32) -> Result<Instruction, DecodeError> {
+    let mnemonic = OPCODES
+        .get((word >> 28) as usize)
+        .ok_or(DecodeError::InvalidOpcode(word))?;
+    let src_tag = word >> 24 & 0xf;
+    let dst_tag = word >> 20 & 0xf;
+    let field = word & LITERAL_MASK;
+    if word & RESERVED_MASK != 0 {
+        return Err(DecodeError::ReservedBits(word));
+    }
+    let src = || -> Result<Src, DecodeError> {
+        match src_tag {
+            TAG_LITERAL => {
+                // sign extend the 11 bits
+                let value = ((field << (16 - LITERAL_BITS)) as u16 as i16) >> (16 - LITERAL_BITS);
+                if !(MIN_VALUE..=MAX_VALUE).contains(&value) {
+                    return Err(DecodeError::LiteralOutOfRange(value));
+                }
+                Ok(Src::Literal(value))
+            }
+            TAG_NONE => Err(DecodeError::InvalidTag(word)),
+            tag => TAGS
+                .get(tag as usize - 2)
+                .copied()
+                .ok_or(DecodeError::InvalidTag(word)),
+        }
+    };
+    let dst = || -> Result<Dst, DecodeError> {
+        match TAGS.get((dst_tag as usize).wrapping_sub(2)) {
+            Some(Src::Register(r)) => Ok(Dst::Register(*r)),
+            Some(Src::Port(p)) => Ok(Dst::Port(*p)),
+            _ => Err(DecodeError::InvalidTag(word)),
+        }
+    };
+    // whatever an instruction does not use has to be zero, so every instruction has one word
+    let unused = |src: bool, dst: bool, field_used: bool| {
+        let stray = (!src && src_tag != TAG_NONE)
+            || (!dst && dst_tag != TAG_NONE)
+            || (!field_used && field != 0);
+        if stray {
+            Err(DecodeError::InvalidTag(word))
+        } else {
+            Ok(())
+        }
+    };
+    let jump = |make: fn(u8) -> Instruction| {
+        unused(false, false, true)?;
+        if field as usize >= INSTRUCTIONS_PER_NODE {
+            return Err(DecodeError::InvalidJump(word));
+        }
+        Ok(make(field as u8))
+    };
+    let single = |make: fn(Src) -> Instruction| {
+        unused(true, false, src_tag == TAG_LITERAL)?;
+        Ok(make(src()?))
+    };
+    let bare = |instruction: Instruction| unused(false, false, false).map(|_| instruction);
+    match *mnemonic {
+        "MOV" => {
+            unused(true, true, src_tag == TAG_LITERAL)?;
+            Ok(Instruction::Mov(src()?, dst()?))
+        }
+        "ADD" => single(Instruction::Add),
+        "SUB" => single(Instruction::Sub),
+        "JRO" => single(Instruction::Jro),
+        "JMP" => jump(Instruction::Jmp),
+        "JEZ" => jump(Instruction::Jez),
+        "JNZ" => jump(Instruction::Jnz),
+        "JGZ" => jump(Instruction::Jgz),
+        "JLZ" => jump(Instruction::Jlz),
+        "SAV" => bare(Instruction::Sav),
+        "SWP" => bare(Instruction::Swp),
+        "NEG" => bare(Instruction::Neg),
+        _ => bare(Instruction::Hcf),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], DecodeError> {
+        if self.bytes.len() < n {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+impl ExecutionPlane {
+    pub fn to_bytecode(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(NODES_PER_PLANE as u8);
+        for kind in self.layout {
+            bytes.push(KINDS.iter().position(|k| *k == kind).unwrap() as u8);
+        }
+        for node in 0..NODES_PER_PLANE {
+            let start = node * INSTRUCTIONS_PER_NODE;
+            let program: Vec<Instruction> = self.instructions[start..start + INSTRUCTIONS_PER_NODE]
+                .iter()
+                .map_while(|i| *i)
+                .collect();
+            let breakpoints = self.breakpoints[node]
+                .iter()
+                .take(program.len())
+                .enumerate()
+                .filter(|(_, set)| **set)
+                .fold(0u32, |mask, (i, _)| mask | 1 << i);
+            bytes.push(program.len() as u8);
+            bytes.extend(breakpoints.to_le_bytes());
+            for instruction in program {
+                bytes.extend(encode_instruction(instruction)?.to_le_bytes());
+            }
+        }
+        Ok(bytes)
+    }
+
+    // sources are not part of the encoding, a decoded plane has none
+    pub fn from_bytecode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader { bytes };
+        if reader
+            .take(MAGIC.len())
+            .map_err(|_| DecodeError::BadMagic)?
+            != MAGIC
+        {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = reader.u8()?;
+        if version == 0 || version > VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let nodes = reader.u8()?;
+        if nodes as usize != NODES_PER_PLANE {
+            return Err(DecodeError::NodeCount(nodes));
+        }
+        let mut layout = [NodeKind::Compute; NODES_PER_PLANE];
+        if version >= 2 {
+            for kind in layout.iter_mut() {
+                let tag = reader.u8()?;
+                *kind = *KINDS
+                    .get(tag as usize)
+                    .ok_or(DecodeError::InvalidNodeKind(tag))?;
+            }
+        }
+        let mut plane = Self::with_layout(layout);
+        for node in 0..nodes {
+            let count = reader.u8()?;
+            if count as usize > INSTRUCTIONS_PER_NODE {
+                return Err(DecodeError::TooManyInstructions { node, count });
+            }
+            if count > 0 && layout[node as usize] != NodeKind::Compute {
+                return Err(DecodeError::NoCode(node));
+            }
+            let breakpoints = reader.u32()?;
+            let mut program = Vec::new();
+            for _ in 0..count {
+                program.push(decode_instruction(reader.u32()?)?);
+            }
+            if count > 0 {
+                for (slot, instruction) in plane
+                    .get_node_instructions_mut(node)
+                    .iter_mut()
+                    .zip(program)
+                {
+                    *slot = Some(instruction);
+                }
+            }
+            for (i, set) in plane.breakpoints[node as usize].iter_mut().enumerate() {
+                *set = (i as u8) < count && breakpoints & 1 << i != 0;
+            }
+        }
+        if !reader.bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes(reader.bytes.len()));
+        }
+        Ok(plane)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn every_src() -> Vec<Src> {
+        let mut srcs: Vec<Src> = (MIN_VALUE..=MAX_VALUE).map(Src::Literal).collect();
+        srcs.extend(TAGS);
+        srcs
+    }
+
+    fn every_instruction() -> Vec<Instruction> {
+        let dsts: Vec<Dst> = TAGS
+            .iter()
+            .map(|t| match t {
+                Src::Register(r) => Dst::Register(*r),
+                Src::Port(p) => Dst::Port(*p),
+                Src::Literal(_) => unreachable!(),
+            })
+            .collect();
+        let mut instructions = Vec::new();
+        for src in every_src() {
+            instructions.extend(dsts.iter().map(|dst| Instruction::Mov(src, *dst)));
+            instructions.extend([
+                Instruction::Add(src),
+                Instruction::Sub(src),
+                Instruction::Jro(src),
+            ]);
+        }
+        for target in 0..INSTRUCTIONS_PER_NODE as u8 {
+            instructions.extend([
+                Instruction::Jmp(target),
+                Instruction::Jez(target),
+                Instruction::Jnz(target),
+                Instruction::Jgz(target),
+                Instruction::Jlz(target),
+            ]);
+        }
+        instructions.extend([
+            Instruction::Sav,
+            Instruction::Swp,
+            Instruction::Neg,
+            Instruction::Hcf,
+        ]);
+        instructions
+    }
+
+    #[test]
+    fn bytecode_round_trips_every_instruction() {
+        let mut words = std::collections::HashSet::new();
+        for instruction in every_instruction() {
+            let word = encode_instruction(instruction).unwrap();
+            assert_eq!(Ok(instruction), decode_instruction(word), "{:#010x}", word);
+            // no two instructions share a word
+            assert!(words.insert(word), "{}", instruction);
+        }
+    }
+
+    #[test]
+    fn bytecode_every_decodable_word_is_canonical() {
+        // opcode and both tags, with a couple of interesting fields
+        for high in 0..1u32 << 12 {
+            for field in [
+                0,
+                1,
+                20,
+                21,
+                999,
+                1000,
+                0x7ff & (-999i32 as u32),
+                LITERAL_MASK,
+            ] {
+                let word = high << 20 | field;
+                if let Ok(instruction) = decode_instruction(word) {
+                    assert_eq!(Ok(word), encode_instruction(instruction), "{:#010x}", word);
+                }
+            }
+        }
+        assert_eq!(
+            Err(DecodeError::ReservedBits(1 << 11)),
+            decode_instruction(1 << 11)
+        );
+        assert_eq!(
+            Err(DecodeError::InvalidOpcode(13 << 28)),
+            decode_instruction(13 << 28)
+        );
+    }
+
+    #[test]
+    fn bytecode_layout_is_stable() {
+        let mov = Instruction::Mov(Src::Literal(-1), Dst::Port(Port::True(TruePort::Down)));
+        assert_eq!(Ok(0x0150_07ff), encode_instruction(mov));
+        assert_eq!(Ok(0x4000_0003), encode_instruction(Instruction::Jmp(3)));
+        assert_eq!(Ok(0xc000_0000), encode_instruction(Instruction::Hcf));
+    }
+
+    #[test]
+    fn bytecode_refuses_what_it_cannot_decode() {
+        for value in [MIN_VALUE, MAX_VALUE] {
+            let word = encode_instruction(Instruction::Add(Src::Literal(value))).unwrap();
+            assert_eq!(
+                Ok(Instruction::Add(Src::Literal(value))),
+                decode_instruction(word)
+            );
+        }
+        for value in [MIN_VALUE - 1, MAX_VALUE + 1, 5000, i16::MIN] {
+            let mov = Instruction::Mov(Src::Literal(value), Dst::Register(Register::Acc));
+            assert_eq!(
+                Err(EncodeError::LiteralOutOfRange(value)),
+                encode_instruction(mov)
+            );
+        }
+        let last = INSTRUCTIONS_PER_NODE as u8 - 1;
+        assert!(encode_instruction(Instruction::Jez(last)).is_ok());
+        for target in [last + 1, 255] {
+            assert_eq!(
+                Err(EncodeError::InvalidJump(target)),
+                encode_instruction(Instruction::Jmp(target))
+            );
+        }
+
+        let mut plane = ExecutionPlane::new();
+        plane.get_node_instructions_mut(4)[0] = Some(Instruction::Add(Src::Literal(1000)));
+        assert_eq!(
+            Err(EncodeError::LiteralOutOfRange(1000)),
+            plane.to_bytecode()
+        );
+    }
+
+    #[test]
+    fn bytecode_plane_round_trip() {
+        let mut plane = ExecutionPlane::new();
+        plane
+            .load_node(0, "L: MOV UP, DOWN\n!ADD -999\nJMP L")
+            .unwrap();
+        plane.load_node(11, "!HCF").unwrap();
+        let bytes = plane.to_bytecode().unwrap();
+        assert_eq!(b"T21B\x02\x0c", &bytes[..6]);
+        assert_eq!(b"\x03\x02\x00\x00\x00", &bytes[18..23]);
+        let mut decoded = ExecutionPlane::from_bytecode(&bytes).unwrap();
+        for node in 0..NODES_PER_PLANE as u8 {
+            assert_eq!(
+                plane.get_node_instructions_mut(node).to_vec(),
+                decoded.get_node_instructions_mut(node).to_vec()
+            );
+        }
+        assert_eq!(plane.breakpoints, decoded.breakpoints);
+        assert_eq!(Ok(bytes), decoded.to_bytecode());
+        assert_eq!(plane.disassemble(), decoded.disassemble());
+    }
+
+    #[test]
+    fn bytecode_plane_errors() {
+        let bytes = ExecutionPlane::new().to_bytecode().unwrap();
+        assert_eq!(6 + 6 * NODES_PER_PLANE, bytes.len());
+        let decode = |bytes: &[u8]| ExecutionPlane::from_bytecode(bytes).err();
+        assert_eq!(Some(DecodeError::BadMagic), decode(b"T21"));
+        assert_eq!(Some(DecodeError::BadMagic), decode(b"NOPE\x01"));
+
+        let mut modified = bytes.clone();
+        modified[4] = 3;
+        assert_eq!(Some(DecodeError::UnsupportedVersion(3)), decode(&modified));
+
+        assert_eq!(
+            Some(DecodeError::Truncated),
+            decode(&bytes[..bytes.len() - 1])
+        );
+
+        let mut modified = bytes.clone();
+        modified.push(0);
+        assert_eq!(Some(DecodeError::TrailingBytes(1)), decode(&modified));
+
+        let mut modified = bytes.clone();
+        modified[5] = 11;
+        assert_eq!(Some(DecodeError::NodeCount(11)), decode(&modified));
+
+        let mut modified = bytes.clone();
+        modified[18] = 22;
+        assert_eq!(
+            Some(DecodeError::TooManyInstructions { node: 0, count: 22 }),
+            decode(&modified)
+        );
+    }
+
+    #[test]
+    fn bytecode_keeps_the_layout() {
+        let mut layout = [NodeKind::Compute; NODES_PER_PLANE];
+        layout[1] = NodeKind::Stack;
+        layout[6] = NodeKind::Damaged;
+        let mut plane = ExecutionPlane::with_layout(layout);
+        plane.load_node(0, "MOV 1, RIGHT").unwrap();
+        let bytes = plane.to_bytecode().unwrap();
+        assert_eq!(&[0, 1, 0, 0, 0, 0, 2], &bytes[6..13]);
+        let decoded = ExecutionPlane::from_bytecode(&bytes).unwrap();
+        assert_eq!(&layout, decoded.layout());
+        assert_eq!(plane.disassemble(), decoded.disassemble());
+        assert_eq!(Ok(bytes.clone()), decoded.to_bytecode());
+
+        let decode = |bytes: &[u8]| ExecutionPlane::from_bytecode(bytes).err();
+        let mut modified = bytes.clone();
+        modified[7] = 3;
+        assert_eq!(Some(DecodeError::InvalidNodeKind(3)), decode(&modified));
+        // node 0 has code, it cannot be a stack
+        let mut modified = bytes.clone();
+        modified[6] = 1;
+        assert_eq!(Some(DecodeError::NoCode(0)), decode(&modified));
+    }
+
+    #[test]
+    fn bytecode_reads_version_one() {
+        let mut plane = ExecutionPlane::new();
+        plane.load_node(3, "NEG").unwrap();
+        let mut bytes = plane.to_bytecode().unwrap();
+        bytes[4] = 1;
+        bytes.drain(6..6 + NODES_PER_PLANE);
+        let mut decoded = ExecutionPlane::from_bytecode(&bytes).unwrap();
+        assert_eq!(
+            Some(Instruction::Neg),
+            decoded.get_node_instructions_mut(3)[0]
+        );
+    }
+}
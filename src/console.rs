@@ -0,0 +1,272 @@
+use std::fmt;
+
+// the visual console some puzzles draw on, fed from a bottom port
+//
+// the stream is x, then y, then colours drawn left to right from there, until a
+// negative value starts over with a new x
+
+pub const WIDTH: usize = 30;
+pub const HEIGHT: usize = 18;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Colour {
+    #[default]
+    Black,
+    DarkGrey,
+    LightGrey,
+    White,
+    Red,
+}
+
+static COLOURS: [Colour; 5] = [
+    Colour::Black,
+    Colour::DarkGrey,
+    Colour::LightGrey,
+    Colour::White,
+    Colour::Red,
+];
+
+impl Colour {
+    // anything past red draws black
+    pub fn from_value(value: i16) -> Self {
+        usize::try_from(value)
+            .ok()
+            .and_then(|i| COLOURS.get(i))
+            .copied()
+            .unwrap_or_default()
+    }
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match self {
+            Self::Black => (0, 0, 0),
+            Self::DarkGrey => (70, 70, 70),
+            Self::LightGrey => (170, 170, 170),
+            Self::White => (255, 255, 255),
+            Self::Red => (200, 30, 30),
+        }
+    }
+    fn ascii(&self) -> char {
+        match self {
+            Self::Black => '.',
+            Self::DarkGrey => '-',
+            Self::LightGrey => '+',
+            Self::White => '#',
+            Self::Red => 'R',
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    // row major
+    pixels: Vec<Colour>,
+}
+
+impl Default for Image {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Image {
+    pub fn new() -> Self {
+        Self {
+            pixels: vec![Colour::Black; WIDTH * HEIGHT],
+        }
+    }
+    pub fn get(&self, x: usize, y: usize) -> Colour {
+        self.pixels[y * WIDTH + x]
+    }
+    pub fn set(&mut self, x: usize, y: usize, colour: Colour) {
+        self.pixels[y * WIDTH + x] = colour;
+    }
+    // the inverse of the ascii dump, None unless it is exactly WIDTH x HEIGHT
+    pub fn from_ascii(text: &str) -> Option<Self> {
+        let rows: Vec<&str> = text.lines().collect();
+        if rows.len() != HEIGHT {
+            return None;
+        }
+        let mut pixels = Vec::with_capacity(WIDTH * HEIGHT);
+        for row in rows {
+            let start = pixels.len();
+            for c in row.chars() {
+                pixels.push(*COLOURS.iter().find(|colour| colour.ascii() == c)?);
+            }
+            if pixels.len() - start != WIDTH {
+                return None;
+            }
+        }
+        Some(Self { pixels })
+    }
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::with_capacity((WIDTH + 1) * HEIGHT);
+        for row in self.pixels.chunks_exact(WIDTH) {
+            text.extend(row.iter().map(Colour::ascii));
+            text.push('\n');
+        }
+        text
+    }
+    // plain text ppm, so it can go straight into a test log
+    pub fn to_ppm(&self) -> String {
+        let mut text = format!("P3\n{} {}\n255\n", WIDTH, HEIGHT);
+        for row in self.pixels.chunks_exact(WIDTH) {
+            let line: Vec<String> = row
+                .iter()
+                .map(|colour| {
+                    let (r, g, b) = colour.rgb();
+                    format!("{} {} {}", r, g, b)
+                })
+                .collect();
+            text.push_str(&line.join(" "));
+            text.push('\n');
+        }
+        text
+    }
+    pub fn differences(&self, other: &Image) -> usize {
+        self.pixels
+            .iter()
+            .zip(&other.pixels)
+            .filter(|(a, b)| a != b)
+            .count()
+    }
+}
+
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_ascii())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Cursor {
+    X,
+    Y { x: usize },
+    Draw { x: usize, y: usize },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Console {
+    image: Image,
+    expected: Option<Image>,
+    cursor: Cursor,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self {
+            image: Image::new(),
+            expected: None,
+            cursor: Cursor::X,
+        }
+    }
+    pub fn expecting(expected: Image) -> Self {
+        Self {
+            expected: Some(expected),
+            ..Self::new()
+        }
+    }
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+    pub fn expected(&self) -> Option<&Image> {
+        self.expected.as_ref()
+    }
+    // pixels that still differ from the expected image, None without one
+    pub fn differences(&self) -> Option<usize> {
+        Some(self.image.differences(self.expected.as_ref()?))
+    }
+    pub fn matches(&self) -> bool {
+        self.differences() == Some(0)
+    }
+    pub(crate) fn receive(&mut self, value: i16) {
+        if value < 0 {
+            self.cursor = Cursor::X;
+            return;
+        }
+        self.cursor = match self.cursor {
+            Cursor::X => Cursor::Y { x: value as usize },
+            Cursor::Y { x } => Cursor::Draw {
+                x,
+                y: value as usize,
+            },
+            Cursor::Draw { x, y } => {
+                // off screen pixels are dropped, rows do not wrap
+                if x < WIDTH && y < HEIGHT {
+                    self.image.set(x, y, Colour::from_value(value));
+                }
+                Cursor::Draw { x: x + 1, y }
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ExecutionPlane, Plane};
+
+    #[test]
+    fn console_protocol() {
+        let mut console = Console::new();
+        for value in [1, 2, 3, 4, -1, 29, 17, 2, 2, -1, 0, 0, 9] {
+            console.receive(value);
+        }
+        assert_eq!(Colour::White, console.image().get(1, 2));
+        assert_eq!(Colour::Red, console.image().get(2, 2));
+        assert_eq!(Colour::LightGrey, console.image().get(29, 17));
+        // the second 2 fell off the right edge instead of wrapping, 9 is no colour
+        assert_eq!(3, console.image().differences(&Image::new()));
+        assert_eq!(Colour::Black, console.image().get(0, 0));
+        assert_eq!(None, console.differences());
+    }
+
+    #[test]
+    fn console_ascii_round_trip() {
+        let mut image = Image::new();
+        image.set(0, 0, Colour::Red);
+        image.set(29, 17, Colour::DarkGrey);
+        let text = image.to_ascii();
+        assert!(text.starts_with("R....."));
+        assert_eq!(Some(image), Image::from_ascii(&text));
+        assert_eq!(None, Image::from_ascii("R\n"));
+        assert_eq!(None, Image::from_ascii(&text.replace('R', "?")));
+    }
+
+    #[test]
+    fn console_ppm() {
+        let mut image = Image::new();
+        image.set(1, 0, Colour::White);
+        let ppm = image.to_ppm();
+        assert!(ppm.starts_with("P3\n30 18\n255\n0 0 0 255 255 255 0 0 0 "));
+        assert_eq!(3 + HEIGHT, ppm.lines().count());
+    }
+
+    #[test]
+    fn console_on_a_plane() {
+        let mut expected = Image::new();
+        for x in 3..6 {
+            expected.set(x, 4, Colour::White);
+        }
+        let mut plane = ExecutionPlane::new();
+        plane.attach_console(30, Console::expecting(expected));
+        plane
+            .load_node(11, "MOV 3, DOWN\nMOV 4, DOWN\nMOV 3, DOWN\nMOV 3, DOWN\nMOV 3, DOWN\nMOV -1, DOWN\nL: JMP L")
+            .unwrap();
+        for _ in 0..8 {
+            plane.step();
+        }
+        assert_eq!(Some(1), plane.console(30).unwrap().differences());
+        for _ in 0..8 {
+            plane.step();
+        }
+        assert!(plane.console(30).unwrap().matches());
+        assert_eq!(None, plane.output(30));
+        assert!(plane.detach_output(30).is_none());
+        assert!(plane.detach_console(30).is_some());
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+
+use super::{
+    clamp, Dst, ExecutionPlane, Instruction, Port, Register, Src, TruePort, INSTRUCTIONS_PER_NODE,
+};
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Acc => "ACC",
+            Self::Nil => "NIL",
+        })
+    }
+}
+
+impl fmt::Display for TruePort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Up => "UP",
+            Self::Down => "DOWN",
+            Self::Left => "LEFT",
+            Self::Right => "RIGHT",
+            Self::Any => "ANY",
+        })
+    }
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::True(p) => p.fmt(f),
+            Self::Last => f.write_str("LAST"),
+        }
+    }
+}
+
+impl fmt::Display for Src {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Port(p) => p.fmt(f),
+            Self::Register(r) => r.fmt(f),
+            // the VM clamps literals when it reads them, so this runs the same and still parses
+            Self::Literal(v) => clamp(*v).fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Dst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Port(p) => p.fmt(f),
+            Self::Register(r) => r.fmt(f),
+        }
+    }
+}
+
+// jump targets have no names left after parsing, so they are named after the index
+pub fn label(target: u8) -> String {
+    format!("L{}", target)
+}
+
+impl Instruction {
+    pub fn jump_target(&self) -> Option<u8> {
+        match self {
+            Self::Jmp(t) | Self::Jez(t) | Self::Jnz(t) | Self::Jgz(t) | Self::Jlz(t) => Some(*t),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Mov(src, dst) => write!(f, "MOV {}, {}", src, dst),
+            Self::Add(src) => write!(f, "ADD {}", src),
+            Self::Sub(src) => write!(f, "SUB {}", src),
+            Self::Jro(src) => write!(f, "JRO {}", src),
+            Self::Jmp(t) => write!(f, "JMP {}", label(*t)),
+            Self::Jez(t) => write!(f, "JEZ {}", label(*t)),
+            Self::Jnz(t) => write!(f, "JNZ {}", label(*t)),
+            Self::Jgz(t) => write!(f, "JGZ {}", label(*t)),
+            Self::Jlz(t) => write!(f, "JLZ {}", label(*t)),
+            Self::Sav => f.write_str("SAV"),
+            Self::Swp => f.write_str("SWP"),
+            Self::Neg => f.write_str("NEG"),
+            Self::Hcf => f.write_str("HCF"),
+        }
+    }
+}
+
+fn retarget(instruction: Instruction, target: u8) -> Instruction {
+    match instruction {
+        Instruction::Jmp(_) => Instruction::Jmp(target),
+        Instruction::Jez(_) => Instruction::Jez(target),
+        Instruction::Jnz(_) => Instruction::Jnz(target),
+        Instruction::Jgz(_) => Instruction::Jgz(target),
+        Instruction::Jlz(_) => Instruction::Jlz(target),
+        instruction => instruction,
+    }
+}
+
+pub fn disassemble_node(instructions: &[Option<Instruction>]) -> String {
+    // same notion of program as the plane, everything up to the first empty slot
+    let program: Vec<Instruction> = instructions.iter().map_while(|i| *i).collect();
+    let len = program.len();
+    // a jump past the end lands on the top once fetch wraps, so it gets the top's label
+    let program: Vec<Instruction> = program
+        .into_iter()
+        .map(|i| match i.jump_target() {
+            Some(t) if t as usize >= len => retarget(i, 0),
+            _ => i,
+        })
+        .collect();
+    let mut targets = [false; INSTRUCTIONS_PER_NODE];
+    for target in program.iter().filter_map(|i| i.jump_target()) {
+        targets[target as usize] = true;
+    }
+    let mut text = String::new();
+    for (i, instruction) in program.iter().enumerate() {
+        if targets[i] {
+            text.push_str(&label(i as u8));
+            text.push_str(":\n");
+        }
+        text.push_str(&instruction.to_string());
+        text.push('\n');
+    }
+    text
+}
+
+impl ExecutionPlane {
+    pub fn disassemble(&self) -> String {
+        // laid out as a save file so it loads straight back in
+        let mut text = String::new();
+        for (i, node) in self.compute_nodes().enumerate() {
+            let start = node as usize * INSTRUCTIONS_PER_NODE;
+            let instructions = &self.instructions[start..start + INSTRUCTIONS_PER_NODE];
+            text.push_str(&format!("@{}\n{}\n", i, disassemble_node(instructions)));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parse::parse_node;
+    use crate::NODES_PER_PLANE;
+
+    fn round_trip(source: &str) -> String {
+        let program: Vec<Option<Instruction>> =
+            parse_node(source).unwrap().into_iter().map(Some).collect();
+        let text = disassemble_node(&program);
+        let reparsed: Vec<Option<Instruction>> =
+            parse_node(&text).unwrap().into_iter().map(Some).collect();
+        assert_eq!(program, reparsed, "{}", text);
+        text
+    }
+
+    #[test]
+    fn display_instructions() {
+        let cases = [
+            (
+                Instruction::Mov(Src::Literal(42), Dst::Port(Port::True(TruePort::Right))),
+                "MOV 42, RIGHT",
+            ),
+            (
+                Instruction::Mov(Src::Port(Port::Last), Dst::Register(Register::Nil)),
+                "MOV LAST, NIL",
+            ),
+            (Instruction::Add(Src::Register(Register::Acc)), "ADD ACC"),
+            (Instruction::Sub(Src::Literal(-7)), "SUB -7"),
+            (
+                Instruction::Jro(Src::Port(Port::True(TruePort::Any))),
+                "JRO ANY",
+            ),
+            (Instruction::Jmp(3), "JMP L3"),
+            (Instruction::Jlz(0), "JLZ L0"),
+            (Instruction::Swp, "SWP"),
+            (Instruction::Hcf, "HCF"),
+        ];
+        for (instruction, text) in cases {
+            assert_eq!(text, instruction.to_string());
+        }
+    }
+
+    #[test]
+    fn disassemble_wraps_jumps_past_the_end() {
+        let program = [
+            Some(Instruction::Jmp(200)),
+            Some(Instruction::Jez(5)),
+            Some(Instruction::Neg),
+        ];
+        let text = disassemble_node(&program);
+        assert_eq!("L0:\nJMP L0\nJEZ L0\nNEG\n", text);
+        let reparsed = parse_node(&text).unwrap();
+        assert_eq!(
+            vec![Instruction::Jmp(0), Instruction::Jez(0), Instruction::Neg],
+            reparsed
+        );
+    }
+
+    #[test]
+    fn disassemble_labels() {
+        let text = round_trip("start: mov up acc\nloop: sub 1\njgz loop\njmp start");
+        assert_eq!("L0:\nMOV UP, ACC\nL1:\nSUB 1\nJGZ L1\nJMP L0\n", text);
+    }
+
+    #[test]
+    fn disassemble_round_trips() {
+        round_trip("MOV ANY, LAST\nADD NIL\nSAV\nSWP\nNEG\nJRO -2\nJEZ E\nJNZ E\nE: JLZ E\nHCF");
+        round_trip("");
+        // hand-built literals past the range come out as what the VM would use
+        let program = [
+            Some(Instruction::Add(Src::Literal(5000))),
+            Some(Instruction::Mov(
+                Src::Literal(i16::MIN),
+                Dst::Register(Register::Acc),
+            )),
+        ];
+        let text = disassemble_node(&program);
+        assert_eq!("ADD 999\nMOV -999, ACC\n", text);
+        assert_eq!(
+            vec![
+                Instruction::Add(Src::Literal(999)),
+                Instruction::Mov(Src::Literal(-999), Dst::Register(Register::Acc)),
+            ],
+            parse_node(&text).unwrap()
+        );
+    }
+
+    #[test]
+    fn disassemble_plane_round_trips() {
+        let mut plane = ExecutionPlane::new();
+        plane.load_node(0, "L: MOV 1, DOWN\nJMP L").unwrap();
+        plane
+            .load_node(4, "mov up, acc\nadd acc\nmov acc, right")
+            .unwrap();
+        let text = plane.disassemble();
+        assert!(text.starts_with("@0\nL0:\nMOV 1, DOWN\nJMP L0\n\n@1\n\n@2\n"));
+        let mut reloaded = ExecutionPlane::from_save(&text).unwrap();
+        for i in 0..NODES_PER_PLANE as u8 {
+            let expected = plane.get_node_instructions_mut(i).to_vec();
+            assert_eq!(expected, reloaded.get_node_instructions_mut(i));
+        }
+        assert_eq!(text, reloaded.disassemble());
+    }
+}
@@ -0,0 +1,249 @@
+use super::parse::{parse_program, parse_src, strip_comment, tokenize, ParseError};
+use super::save::{section_body, split_sections, SaveError, SaveErrorKind};
+use super::validate::{CHARS_PER_LINE, LINES_PER_NODE};
+
+// canonical layout for node sources, comments survive but whitespace and casing do not
+
+#[derive(Debug, Clone, Default)]
+struct Line {
+    // breakpoint and labels, "!A: B: "
+    prefix: String,
+    instruction: String,
+    // from the '#' on, exactly as written
+    comment: String,
+}
+
+fn width(text: &str) -> usize {
+    text.chars().count()
+}
+
+impl Line {
+    fn head(&self) -> String {
+        if self.instruction.is_empty() {
+            self.prefix.trim_end().to_string()
+        } else {
+            format!("{}{}", self.prefix, self.instruction)
+        }
+    }
+    fn is_blank(&self) -> bool {
+        self.prefix.is_empty() && self.instruction.is_empty() && self.comment.is_empty()
+    }
+}
+
+fn format_instruction(text: &str) -> String {
+    let tokens = tokenize(text, 0);
+    let Some((mnemonic, operands)) = tokens.split_first() else {
+        return String::new();
+    };
+    let mnemonic = mnemonic.text.to_ascii_uppercase();
+    let operands: Vec<String> = operands
+        .iter()
+        .map(|t| match parse_src(t.text) {
+            // jumps take labels, which only need the casing fixed
+            Ok(src) if !mnemonic.starts_with('J') || mnemonic == "JRO" => src.to_string(),
+            _ => t.text.to_ascii_uppercase(),
+        })
+        .collect();
+    if operands.is_empty() {
+        mnemonic
+    } else {
+        format!("{} {}", mnemonic, operands.join(", "))
+    }
+}
+
+fn format_line(line: &str) -> Line {
+    let code = strip_comment(line);
+    let mut formatted = Line {
+        comment: line[code.len()..].trim_end().to_string(),
+        ..Line::default()
+    };
+    let mut rest = code.trim();
+    if let Some(unmarked) = rest.strip_prefix('!') {
+        formatted.prefix.push('!');
+        rest = unmarked.trim_start();
+    }
+    while let Some(colon) = rest.find(':') {
+        formatted
+            .prefix
+            .push_str(&rest[..colon].trim().to_ascii_uppercase());
+        formatted.prefix.push_str(": ");
+        rest = rest[colon + 1..].trim_start();
+    }
+    formatted.instruction = format_instruction(rest);
+    formatted
+}
+
+// the source has to parse, there is no canonical form of something that is not a program
+pub fn format_node(source: &str) -> Result<String, Vec<ParseError>> {
+    parse_program(source)?;
+    let mut lines: Vec<Line> = Vec::new();
+    for line in source.lines().map(format_line) {
+        // runs of blank lines become one, and none at either end
+        if line.is_blank() && lines.last().is_none_or(Line::is_blank) {
+            continue;
+        }
+        lines.push(line);
+    }
+    if lines.last().is_some_and(Line::is_blank) {
+        lines.pop();
+    }
+
+    // a label that pushes its instruction over the limit gets a line of its own
+    let mut count = lines.len();
+    let mut placed = Vec::new();
+    for line in lines {
+        let split = !line.prefix.is_empty()
+            && !line.instruction.is_empty()
+            && width(&line.head()) > CHARS_PER_LINE
+            && count < LINES_PER_NODE;
+        if split {
+            count += 1;
+            placed.push(Line {
+                prefix: line.prefix,
+                ..Line::default()
+            });
+            placed.push(Line {
+                instruction: line.instruction,
+                comment: line.comment,
+                ..Line::default()
+            });
+        } else {
+            placed.push(line);
+        }
+    }
+
+    // trailing comments line up one space after the longest instruction carrying one
+    let column = placed
+        .iter()
+        .filter(|l| !l.comment.is_empty())
+        .map(|l| width(&l.head()))
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut text = Vec::new();
+    for line in placed {
+        let head = line.head();
+        if line.comment.is_empty() || head.is_empty() {
+            text.push(format!("{}{}", head, line.comment));
+            continue;
+        }
+        let fits = |gap: usize| width(&head) + gap + width(&line.comment) <= CHARS_PER_LINE;
+        let gap = [column - width(&head), 1, 0]
+            .into_iter()
+            .find(|&gap| fits(gap));
+        match gap {
+            Some(gap) => text.push(format!("{}{}{}", head, " ".repeat(gap), line.comment)),
+            // no room beside the code, so above it if the node has a line to spare
+            None if count < LINES_PER_NODE => {
+                count += 1;
+                text.push(line.comment);
+                text.push(head);
+            }
+            None => text.push(format!("{} {}", head, line.comment)),
+        }
+    }
+    Ok(text.join("\n"))
+}
+
+pub fn format_save(text: &str) -> Result<String, SaveError> {
+    let mut formatted = String::new();
+    let mut errors = Vec::new();
+    for section in split_sections(text)? {
+        match format_node(section.source()) {
+            Ok(source) => {
+                formatted.push_str(&format!("@{}", section.node));
+                formatted.push_str(&section_body(&source));
+            }
+            // there is no layout here, so '@N' is taken as the grid index
+            Err(e) => errors.extend(e.into_iter().map(|mut e| {
+                e.node = Some(section.node as u8);
+                e.line += section.line;
+                e
+            })),
+        }
+    }
+    if let Some(first) = errors.first() {
+        return Err(SaveError {
+            line: first.line,
+            kind: SaveErrorKind::Parse(errors),
+        });
+    }
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ExecutionPlane;
+
+    fn format(source: &str) -> String {
+        let formatted = format_node(source).unwrap();
+        // formatting is idempotent
+        assert_eq!(formatted, format_node(&formatted).unwrap());
+        formatted
+    }
+
+    #[test]
+    fn format_casing_and_commas() {
+        assert_eq!(
+            "MOV UP, ACC\nADD -7\nJRO LEFT\nNOP\nJMP LOOP\nLOOP: SWP",
+            format("  mov up acc\nadd   -007\n\tjro left\nnop\njmp loop\nloop:swp")
+        );
+        assert_eq!("!START: ADD 1", format("!  start :add 1"));
+        assert_eq!("A: B:\nSUB ACC", format("a:b:\nsub acc"));
+    }
+
+    #[test]
+    fn format_blank_lines() {
+        assert_eq!("SAV\n\nSWP", format("\n\nSAV\n\n\n  \nSWP\n\n"));
+        assert_eq!("", format("\n\n"));
+    }
+
+    #[test]
+    fn format_aligns_comments() {
+        assert_eq!(
+            "# read\nMOV UP, ACC #a\nNEG         #b\nL:\nJMP L",
+            format("# read\nmov up,acc #a\nneg #b\nl:\njmp l")
+        );
+    }
+
+    #[test]
+    fn format_stays_within_the_line_limit() {
+        // label moved off the line
+        assert_eq!("LONG:\nMOV LEFT, RIGHT", format("long:mov left,right"));
+        // comments squeeze in, then move above
+        assert_eq!(
+            "MOV LEFT, ACC #one\nMOV LEFT, RIGHT#ab\n#two\nMOV -999, RIGHT",
+            format("mov left acc #one\nmov left right#ab\nmov -999 right #two")
+        );
+        // no lines to spare, so it is left over long
+        let full = "NOP\n".repeat(LINES_PER_NODE - 1);
+        let formatted = format(&format!("{}mov -999 right #two", full));
+        assert!(formatted.ends_with("\nMOV -999, RIGHT #two"));
+        assert_eq!(LINES_PER_NODE, formatted.lines().count());
+    }
+
+    #[test]
+    fn format_rejects_invalid_source() {
+        assert!(format_node("SAV\nFOO").is_err());
+    }
+
+    #[test]
+    fn format_save_files() {
+        let text = "@0\nmov up,down\n\n@1\n\n\n@2\ns: mov up acc #c\n\n\njgz s\n";
+        let formatted = format_save(text).unwrap();
+        assert_eq!(
+            "@0\nMOV UP, DOWN\n\n@1\n\n\n@2\nS: MOV UP, ACC #c\n\nJGZ S\n\n",
+            formatted
+        );
+        assert_eq!(formatted, format_save(&formatted).unwrap());
+        ExecutionPlane::from_save(&formatted).unwrap();
+
+        let err = format_save("@0\nNOP\n\n@1\nNOP\nBAD\n").unwrap_err();
+        assert_eq!(6, err.line);
+        let SaveErrorKind::Parse(errors) = err.kind else {
+            panic!("expected parse errors");
+        };
+        assert_eq!(Some(1), errors[0].node);
+    }
+}
@@ -0,0 +1,318 @@
+use std::fmt;
+
+// just enough json for the language server, the crate has no dependencies to lean on
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // kept in insertion order so output is predictable
+    Object(Vec<(String, Value)>),
+}
+
+static NULL: Value = Value::Null;
+
+impl Value {
+    pub fn object<'a>(fields: impl IntoIterator<Item = (&'a str, Value)>) -> Self {
+        Self::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+    // missing keys and non-objects give null, so lookups can be chained
+    pub fn get(&self, key: &str) -> &Value {
+        match self {
+            Self::Object(fields) => fields
+                .iter()
+                .find(|(k, _)| k == key)
+                .map_or(&NULL, |(_, v)| v),
+            _ => &NULL,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+    pub fn is_null(&self) -> bool {
+        matches!(self, Self::Null)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::String(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::String(s)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(n: usize) -> Self {
+        Self::Number(n as f64)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(values: Vec<Value>) -> Self {
+        Self::Array(values)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => b.fmt(f),
+            Self::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Self::Number(n) => n.fmt(f),
+            Self::String(s) => write_string(f, s),
+            Self::Array(values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    value.fmt(f)?;
+                }
+                f.write_str("]")
+            }
+            Self::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    f.write_str(":")?;
+                    value.fmt(f)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct JsonError {
+    // byte offset of whatever could not be parsed
+    pub offset: usize,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid json at byte {}", self.offset)
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+struct Parser<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self) -> Result<T, JsonError> {
+        Err(JsonError {
+            offset: self.offset,
+        })
+    }
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.offset).copied()
+    }
+    fn expect(&mut self, literal: &str) -> Result<(), JsonError> {
+        if !self.text[self.offset..].starts_with(literal) {
+            return self.error();
+        }
+        self.offset += literal.len();
+        Ok(())
+    }
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Value::Null)?,
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true))?,
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false))?,
+            Some(b'"') => Value::String(self.string()?),
+            Some(b'[') => {
+                self.offset += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.offset += 1;
+                } else {
+                    loop {
+                        values.push(self.value()?);
+                        self.skip_whitespace();
+                        match self.peek() {
+                            Some(b',') => self.offset += 1,
+                            Some(b']') => break self.offset += 1,
+                            _ => return self.error(),
+                        }
+                    }
+                }
+                Value::Array(values)
+            }
+            Some(b'{') => {
+                self.offset += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.offset += 1;
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        let key = self.string()?;
+                        self.skip_whitespace();
+                        self.expect(":")?;
+                        fields.push((key, self.value()?));
+                        self.skip_whitespace();
+                        match self.peek() {
+                            Some(b',') => self.offset += 1,
+                            Some(b'}') => break self.offset += 1,
+                            _ => return self.error(),
+                        }
+                    }
+                }
+                Value::Object(fields)
+            }
+            _ => self.number()?,
+        };
+        Ok(value)
+    }
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        let mut chars = self.text[self.offset..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.offset += i + 1;
+                    return Ok(s);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            let unit = u32::from_str_radix(&hex, 16).map_err(|_| JsonError {
+                                offset: self.offset + i,
+                            })?;
+                            // surrogate pairs are not worth it here
+                            char::from_u32(unit).unwrap_or('\u{fffd}')
+                        }
+                        Some(c) => c,
+                        None => break,
+                    };
+                    s.push(escaped);
+                }
+                c => s.push(c),
+            }
+        }
+        self.error()
+    }
+    fn number(&mut self) -> Result<Value, JsonError> {
+        let rest = &self.text[self.offset..];
+        let length = rest
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(rest.len());
+        let n = rest[..length].parse().or_else(|_| self.error())?;
+        self.offset += length;
+        Ok(Value::Number(n))
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value, JsonError> {
+    let mut parser = Parser { text, offset: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.offset != text.len() {
+        return parser.error();
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_round_trip() {
+        let text = r#"{"id":1,"params":{"text":"MOV UP, ACC\n\"q\"","list":[true,false,null,-2.5,[]],"empty":{}}}"#;
+        let value = parse(text).unwrap();
+        assert_eq!(Some(1), value.get("id").as_u64());
+        assert_eq!(
+            Some("MOV UP, ACC\n\"q\""),
+            value.get("params").get("text").as_str()
+        );
+        assert!(value.get("missing").get("deeper").is_null());
+        assert_eq!(text, value.to_string());
+    }
+
+    #[test]
+    fn json_whitespace_and_escapes() {
+        let value = parse(" { \"a\" : [ 1 , 2 ] , \"b\" : \"\\u0041\\t\" } ").unwrap();
+        assert_eq!(Some(2), value.get("a").as_array().unwrap()[1].as_u64());
+        assert_eq!(Some("A\t"), value.get("b").as_str());
+    }
+
+    #[test]
+    fn json_errors() {
+        for text in ["", "{", "[1,]", "{\"a\" 1}", "\"open", "nul", "1 2"] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+}
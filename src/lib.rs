@@ -0,0 +1,1718 @@
+pub mod bytecode;
+pub mod console;
+pub mod disasm;
+pub mod format;
+mod json;
+pub mod lint;
+pub mod lsp;
+pub mod parse;
+pub mod preprocess;
+pub mod save;
+mod stack;
+pub mod stream;
+pub mod validate;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Register {
+    Acc,
+    Nil,
+    // There is also a BAK register but it is not addressable
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TruePort {
+    Up,
+    Down,
+    Left,
+    Right,
+    // originally a pseudoport, but oh well
+    Any,
+}
+
+impl TruePort {
+    fn reverse(&self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Up => Self::Down,
+            Self::Right => Self::Left,
+            Self::Down => Self::Up,
+            Self::Any => panic!("Cannot reverse port 'Any'"),
+        }
+    }
+}
+
+// the order in which ANY looks at the neighbours, same as the game
+static ANY_PRIORITY: [TruePort; 4] = [
+    TruePort::Left,
+    TruePort::Right,
+    TruePort::Up,
+    TruePort::Down,
+];
+
+fn resolve_any(direction: TruePort) -> &'static [TruePort] {
+    match direction {
+        TruePort::Any => &ANY_PRIORITY,
+        TruePort::Up => &ANY_PRIORITY[2..3],
+        TruePort::Down => &ANY_PRIORITY[3..4],
+        TruePort::Left => &ANY_PRIORITY[0..1],
+        TruePort::Right => &ANY_PRIORITY[1..2],
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Port {
+    True(TruePort),
+    Last,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Src {
+    Port(Port),
+    Register(Register),
+    Literal(i16),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Dst {
+    Port(Port),
+    Register(Register),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    Run,
+    Read,
+    Write,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Instruction {
+    Mov(Src, Dst),
+    Add(Src),
+    Sub(Src),
+    Jro(Src),
+    // jump targets are absolute instruction indices within the node
+    Jmp(u8),
+    Jez(u8),
+    Jnz(u8),
+    Jgz(u8),
+    Jlz(u8),
+    Sav,
+    Swp,
+    Neg,
+    Hcf,
+}
+
+impl Instruction {
+    fn get_src(&self) -> Option<Src> {
+        match self {
+            Self::Mov(s, _) | Self::Sub(s) | Self::Add(s) | Self::Jro(s) => Some(*s),
+            _ => None,
+        }
+    }
+    fn is_jump(&self) -> bool {
+        matches!(
+            self,
+            Self::Jro(_) | Self::Jmp(_) | Self::Jez(_) | Self::Jnz(_) | Self::Jgz(_) | Self::Jlz(_)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ExecutionNode {
+    acc: i16,
+    bak: i16,
+    instruction_pointer: u8,
+    instruction_len: u8,
+    current_instruction: Option<Instruction>,
+    port_read_buffer: Option<i16>,
+    port_write_buffer: Option<i16>,
+    direction: Option<TruePort>,
+    last_port: Option<TruePort>,
+    mode: Mode,
+}
+
+impl ExecutionNode {
+    const fn new() -> Self {
+        Self {
+            acc: 0,
+            bak: 0,
+            instruction_pointer: 0,
+            instruction_len: 0,
+            current_instruction: None,
+            port_read_buffer: None,
+            port_write_buffer: None,
+            direction: None,
+            last_port: None,
+            mode: Mode::Run,
+        }
+    }
+    pub fn acc(&self) -> i16 {
+        self.acc
+    }
+    pub fn bak(&self) -> i16 {
+        self.bak
+    }
+    pub fn instruction_pointer(&self) -> u8 {
+        self.instruction_pointer
+    }
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        self.current_instruction
+    }
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+    pub fn last_port(&self) -> Option<TruePort> {
+        self.last_port
+    }
+    fn map_port(&self, port: Port) -> TruePort {
+        match port {
+            Port::True(p) => p,
+            Port::Last => self.last_port.expect("LAST is resolved before use"),
+        }
+    }
+    fn resolve_last(&self, instruction: Instruction) -> Instruction {
+        // LAST acts as NIL until a port has actually been used
+        if self.last_port.is_some() {
+            return instruction;
+        }
+        let src = |src| match src {
+            Src::Port(Port::Last) => Src::Register(Register::Nil),
+            src => src,
+        };
+        let dst = |dst| match dst {
+            Dst::Port(Port::Last) => Dst::Register(Register::Nil),
+            dst => dst,
+        };
+        match instruction {
+            Instruction::Mov(s, d) => Instruction::Mov(src(s), dst(d)),
+            Instruction::Add(s) => Instruction::Add(src(s)),
+            Instruction::Sub(s) => Instruction::Sub(src(s)),
+            Instruction::Jro(s) => Instruction::Jro(src(s)),
+            instruction => instruction,
+        }
+    }
+    fn fetch(&mut self, instructions: &[Option<Instruction>]) {
+        if self.instruction_pointer >= self.instruction_len {
+            self.instruction_pointer = 0;
+        }
+        // an empty node has nothing to fetch and just idles
+        self.current_instruction = instructions[..self.instruction_len as usize]
+            .get(self.instruction_pointer as usize)
+            .copied()
+            .flatten();
+    }
+    fn increment_instruction_pointer(&mut self) {
+        self.instruction_pointer += 1;
+        if self.instruction_pointer >= INSTRUCTIONS_PER_NODE as u8 {
+            self.instruction_pointer = 0;
+        }
+    }
+    fn resolve_write(&mut self, direction: TruePort) {
+        // NOTE move me to a trait?
+        if self.direction == Some(TruePort::Any) {
+            self.last_port = Some(direction);
+        }
+        self.mode = Mode::Run;
+        self.increment_instruction_pointer();
+    }
+    fn read_step(&mut self) {
+        if self.mode == Mode::Read || self.mode == Mode::Write {
+            return;
+        }
+        if let Some(instruction) = self.current_instruction {
+            if let Some(Src::Port(port)) = self.resolve_last(instruction).get_src() {
+                self.mode = Mode::Read;
+                let p = self.map_port(port);
+                self.direction = Some(p);
+                // ANY only becomes LAST once a neighbour actually answers
+                if p != TruePort::Any {
+                    self.last_port = Some(p)
+                }
+            }
+        }
+    }
+    fn step(&mut self) {
+        // resolved here rather than at fetch, a read this cycle may have just set LAST
+        match self.current_instruction.map(|i| self.resolve_last(i)) {
+            Some(Instruction::Mov(src, dst)) => self.mov(src, dst),
+            Some(Instruction::Add(src)) => self.add(src),
+            Some(Instruction::Sub(src)) => self.sub(src),
+            Some(Instruction::Jro(src)) => self.jro(src),
+            Some(Instruction::Jmp(target)) => self.jmp(target),
+            Some(Instruction::Jez(target)) => self.jez(target),
+            Some(Instruction::Jnz(target)) => self.jnz(target),
+            Some(Instruction::Jgz(target)) => self.jgz(target),
+            Some(Instruction::Jlz(target)) => self.jlz(target),
+            Some(Instruction::Sav) => self.sav(),
+            Some(Instruction::Swp) => self.swp(),
+            Some(Instruction::Neg) => self.neg(),
+            // the plane notices and halts, the node itself just stays put
+            Some(Instruction::Hcf) | None => {
+                return;
+            }
+        };
+        if self.mode == Mode::Run && !self.current_instruction.unwrap().is_jump() {
+            self.increment_instruction_pointer();
+        }
+    }
+    fn resolve_src(&mut self, src: Src) -> Option<i16> {
+        // every instruction goes through here, None means we are still waiting on a port
+        match src {
+            Src::Port(_) => {
+                let value = self.port_read_buffer.take()?;
+                // our read was successful so we reset mode
+                self.mode = Mode::Run;
+                Some(value)
+            }
+            Src::Register(Register::Acc) => Some(self.acc),
+            Src::Register(Register::Nil) => Some(0),
+            Src::Literal(value) => Some(clamp(value)),
+        }
+    }
+    fn mov(&mut self, src: Src, dst: Dst) {
+        if self.mode == Mode::Write {
+            // already done reading, just waiting for someone to take the value
+            return;
+        }
+        let Some(value) = self.resolve_src(src) else {
+            return;
+        };
+        match dst {
+            Dst::Port(port) => {
+                self.mode = Mode::Write;
+                self.port_write_buffer = Some(value);
+                let p = self.map_port(port);
+                self.direction = Some(p);
+                if p != TruePort::Any {
+                    self.last_port = Some(p);
+                }
+            }
+            Dst::Register(Register::Acc) => self.acc = value,
+            Dst::Register(Register::Nil) => (),
+        };
+    }
+    fn add(&mut self, src: Src) {
+        if let Some(value) = self.resolve_src(src) {
+            self.acc = clamp(self.acc.saturating_add(value));
+        }
+    }
+    fn sub(&mut self, src: Src) {
+        if let Some(value) = self.resolve_src(src) {
+            self.acc = clamp(self.acc.saturating_sub(value));
+        }
+    }
+    fn jump(&mut self, offset: i16) {
+        let new_pointer = (self.instruction_pointer as i16).saturating_add(offset);
+        if new_pointer < 0 {
+            self.instruction_pointer = 0;
+        } else if new_pointer >= self.instruction_len as i16 {
+            self.instruction_pointer = self.instruction_len - 1;
+        } else {
+            self.instruction_pointer = new_pointer as u8;
+        }
+    }
+    fn jro(&mut self, src: Src) {
+        if let Some(offset) = self.resolve_src(src) {
+            self.jump(offset);
+        }
+    }
+    fn jmp(&mut self, target: u8) {
+        self.instruction_pointer = target;
+    }
+    fn branch(&mut self, condition: bool, target: u8) {
+        if condition {
+            self.jmp(target);
+        } else {
+            self.increment_instruction_pointer();
+        }
+    }
+    fn jez(&mut self, target: u8) {
+        self.branch(self.acc == 0, target);
+    }
+    fn jnz(&mut self, target: u8) {
+        self.branch(self.acc != 0, target);
+    }
+    fn jgz(&mut self, target: u8) {
+        self.branch(self.acc > 0, target);
+    }
+    fn jlz(&mut self, target: u8) {
+        self.branch(self.acc < 0, target);
+    }
+    fn swp(&mut self) {
+        std::mem::swap(&mut self.bak, &mut self.acc);
+    }
+    fn sav(&mut self) {
+        self.bak = self.acc;
+    }
+    fn neg(&mut self) {
+        self.acc = -self.acc;
+    }
+}
+
+// left up right down
+type Neighbours = (Option<u8>, Option<u8>, Option<u8>, Option<u8>);
+
+static NODE_LUT: [Neighbours; NODES_PER_PLANE] = [
+    // left up right down
+    (None, None, Some(1), Some(4)),
+    (Some(0), None, Some(2), Some(5)),
+    (Some(1), None, Some(3), Some(6)),
+    (Some(2), None, None, Some(7)),
+    (None, Some(0), Some(5), Some(8)),
+    (Some(4), Some(1), Some(6), Some(9)),
+    (Some(5), Some(2), Some(7), Some(10)),
+    (Some(6), Some(3), None, Some(11)),
+    (None, Some(4), Some(9), None),
+    (Some(8), Some(5), Some(10), None),
+    (Some(9), Some(6), Some(11), None),
+    (Some(10), Some(7), None, None),
+];
+
+static PORT_LUT: [(u8, u8, u8, u8); NODES_PER_PLANE] = [
+    // left up right down
+    (4, 0, 5, 9),
+    (5, 1, 6, 10),
+    (6, 2, 7, 11),
+    (7, 3, 8, 12),
+    (13, 9, 14, 18),
+    (14, 10, 15, 19),
+    (15, 11, 16, 20),
+    (16, 12, 17, 21),
+    (22, 18, 23, 27),
+    (23, 19, 24, 28),
+    (24, 20, 25, 29),
+    (25, 21, 26, 30),
+];
+
+fn map_port(direction: TruePort, i: usize) -> usize {
+    (match direction {
+        TruePort::Left => PORT_LUT[i].0,
+        TruePort::Up => PORT_LUT[i].1,
+        TruePort::Right => PORT_LUT[i].2,
+        TruePort::Down => PORT_LUT[i].3,
+        TruePort::Any => unreachable!("ANY has to be resolved to a real port"),
+    }) as usize
+}
+
+fn reverse_map_node(direction: TruePort, i: usize) -> Option<u8> {
+    match direction {
+        TruePort::Left => NODE_LUT[i].0,
+        TruePort::Up => NODE_LUT[i].1,
+        TruePort::Right => NODE_LUT[i].2,
+        TruePort::Down => NODE_LUT[i].3,
+        TruePort::Any => unreachable!("ANY has to be resolved to a real port"),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stall {
+    pub node: u8,
+    pub direction: TruePort,
+    pub mode: Mode,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Halt {
+    pub node: u8,
+    pub instruction: u8,
+    pub cycle: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Status {
+    Running,
+    // a node is about to run an instruction marked with '!'
+    Breakpoint { node: u8, instruction: u8 },
+    Halted(Halt),
+}
+
+pub trait Plane {
+    fn step(&mut self) -> Status;
+}
+
+pub const NODES_PER_PLANE: usize = 12;
+pub const INSTRUCTIONS_PER_NODE: usize = 21;
+const PORTS_PER_PLANE: usize = 31;
+pub const MAX_VALUE: i16 = 999;
+pub const MIN_VALUE: i16 = -999;
+pub const STACK_CAPACITY: usize = 15;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum NodeKind {
+    // T21, runs a program
+    #[default]
+    Compute,
+    // T30, pushes whatever its neighbours write and pops for whoever reads
+    Stack,
+    // corrupted tile, holds nothing and never touches its ports
+    Damaged,
+}
+
+// what sits at each grid position, the plain plane is compute nodes all the way
+pub type Layout = [NodeKind; NODES_PER_PLANE];
+
+fn compute_nodes(layout: &Layout) -> impl Iterator<Item = u8> + '_ {
+    (0..NODES_PER_PLANE as u8).filter(|&i| layout[i as usize] == NodeKind::Compute)
+}
+
+// every value a node can hold or pass along is squeezed into the game's range
+fn clamp(value: i16) -> i16 {
+    value.clamp(MIN_VALUE, MAX_VALUE)
+}
+
+#[derive(Debug, Copy, Clone)]
+struct PortValue {
+    value: i16,
+    // needed to withdraw the other offers of an ANY write once one is taken
+    writer: u8,
+}
+
+// an ANY write sits in several ports, only one reader may have it
+fn withdraw(ports: &mut [Option<PortValue>], writer: u8) {
+    for port in ports.iter_mut() {
+        if port.is_some_and(|p| p.writer == writer) {
+            *port = None;
+        }
+    }
+}
+
+pub struct ExecutionPlane {
+    layout: Layout,
+    nodes: [ExecutionNode; NODES_PER_PLANE],
+    // only ever filled at stack positions, the top is the end
+    stacks: [Vec<i16>; NODES_PER_PLANE],
+    inputs: [Option<stream::Input>; stream::INPUT_PORTS.end],
+    outputs: [Option<stream::Sink>; PORTS_PER_PLANE - stream::OUTPUT_PORTS.start],
+    ports: [Option<PortValue>; PORTS_PER_PLANE],
+    queued_writes: [Option<PortValue>; PORTS_PER_PLANE],
+    // writer index and the direction its value was taken in
+    clear_writes: Vec<(u8, TruePort)>,
+    // 1 indexed, the cycle currently or last executed
+    cycle: u64,
+    halt: Option<Halt>,
+    instructions: Box<[Option<Instruction>; NODES_PER_PLANE * INSTRUCTIONS_PER_NODE]>,
+    lengths_dirty: bool,
+    // whether the breakpoints on the first instructions had their check before cycle 1
+    started: bool,
+    // the text each node was loaded from, exactly as it sits in a save file after '@N'
+    sources: [Option<String>; NODES_PER_PLANE],
+    breakpoints: [[bool; INSTRUCTIONS_PER_NODE]; NODES_PER_PLANE],
+}
+
+impl Default for ExecutionPlane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExecutionPlane {
+    pub fn new() -> Self {
+        Self::with_layout([NodeKind::Compute; NODES_PER_PLANE])
+    }
+    pub fn with_layout(layout: Layout) -> Self {
+        const NODE: ExecutionNode = ExecutionNode::new();
+
+        Self {
+            layout,
+            nodes: [NODE; NODES_PER_PLANE],
+            stacks: Default::default(),
+            inputs: Default::default(),
+            outputs: Default::default(),
+            ports: [None; PORTS_PER_PLANE],
+            queued_writes: [None; PORTS_PER_PLANE],
+            clear_writes: Vec::with_capacity(NODES_PER_PLANE),
+            cycle: 0,
+            halt: None,
+            instructions: Box::new([None; NODES_PER_PLANE * INSTRUCTIONS_PER_NODE]),
+            lengths_dirty: false,
+            started: false,
+            sources: Default::default(),
+            breakpoints: [[false; INSTRUCTIONS_PER_NODE]; NODES_PER_PLANE],
+        }
+    }
+    pub fn get_node_instructions_mut(&mut self, index: u8) -> &mut [Option<Instruction>] {
+        // lil helper func
+        if index >= 12 {
+            panic!("12 nodes per plane, 0 indexed");
+        }
+        if self.layout[index as usize] != NodeKind::Compute {
+            panic!("node {} is not a compute node, it holds no code", index);
+        }
+        let start_offset = index as usize * INSTRUCTIONS_PER_NODE;
+        let end_offset = start_offset + INSTRUCTIONS_PER_NODE;
+        // we cannot see what the caller does with the slots, so recount on the next step
+        self.lengths_dirty = true;
+        &mut self.instructions[start_offset..end_offset]
+    }
+    pub fn load_node(&mut self, index: u8, source: &str) -> Result<(), Vec<parse::ParseError>> {
+        self.load_program(index, source)?;
+        self.sources[index as usize] = Some(save::section_body(source));
+        Ok(())
+    }
+    fn load_program(&mut self, index: u8, source: &str) -> Result<(), Vec<parse::ParseError>> {
+        self.check_programmable(index).map_err(|e| vec![e])?;
+        let program = parse::parse_program(source).map_err(|mut errors| {
+            for error in errors.iter_mut() {
+                error.node = Some(index);
+            }
+            errors
+        })?;
+        self.install_program(index, program);
+        Ok(())
+    }
+    pub(crate) fn check_programmable(&self, index: u8) -> Result<(), parse::ParseError> {
+        match self.layout[index as usize] {
+            NodeKind::Compute => Ok(()),
+            kind => Err(parse::ParseError {
+                node: Some(index),
+                line: 1,
+                column: 1,
+                width: 0,
+                kind: parse::ParseErrorKind::NoCode(kind),
+                suggestion: None,
+            }),
+        }
+    }
+    fn install_program(&mut self, index: u8, program: parse::Program) {
+        let slots = self.get_node_instructions_mut(index);
+        slots.fill(None);
+        for (slot, instruction) in slots.iter_mut().zip(program.instructions) {
+            *slot = Some(instruction);
+        }
+        let breakpoints = &mut self.breakpoints[index as usize];
+        breakpoints.fill(false);
+        for i in program.breakpoints {
+            breakpoints[i as usize] = true;
+        }
+    }
+    pub fn stalled(&self) -> Vec<Stall> {
+        // nodes blocked on a port, a write stays pending until something reads it
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.mode != Mode::Run)
+            .filter_map(|(i, node)| {
+                Some(Stall {
+                    node: i as u8,
+                    direction: node.direction?,
+                    mode: node.mode,
+                })
+            })
+            .collect()
+    }
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+    // None unless the node is a stack
+    pub fn stack(&self, index: u8) -> Option<&[i16]> {
+        match self.layout[index as usize] {
+            NodeKind::Stack => Some(&self.stacks[index as usize]),
+            _ => None,
+        }
+    }
+    // grid indices of the nodes a save file numbers, in '@N' order
+    fn compute_nodes(&self) -> impl Iterator<Item = u8> + '_ {
+        compute_nodes(&self.layout)
+    }
+    pub fn node(&self, index: u8) -> &ExecutionNode {
+        &self.nodes[index as usize]
+    }
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+    pub fn halt(&self) -> Option<Halt> {
+        self.halt
+    }
+    pub fn set_breakpoint(&mut self, node: u8, instruction: u8, enabled: bool) {
+        self.breakpoints[node as usize][instruction as usize] = enabled;
+    }
+    // everything up to the first empty slot, same as what runs
+    fn program(&self, node: usize) -> Vec<Instruction> {
+        let start = node * INSTRUCTIONS_PER_NODE;
+        self.instructions[start..start + INSTRUCTIONS_PER_NODE]
+            .iter()
+            .map_while(|i| *i)
+            .collect()
+    }
+    fn set_node_instruction_length(&mut self) {
+        for (node, instructions) in self
+            .nodes
+            .iter_mut()
+            .zip(self.instructions.chunks_exact(INSTRUCTIONS_PER_NODE))
+        {
+            // a program runs up to its first empty slot, same as fetch wrapping around
+            node.instruction_len = instructions.iter().take_while(|i| i.is_some()).count() as u8;
+        }
+        self.lengths_dirty = false;
+    }
+}
+
+impl Plane for ExecutionPlane {
+    fn step(&mut self) -> Status {
+        if let Some(halt) = self.halt {
+            return Status::Halted(halt);
+        }
+        if self.lengths_dirty {
+            self.set_node_instruction_length();
+        }
+        if !self.started {
+            self.started = true;
+            // nothing has run yet, so the later check never saw the first instructions
+            for (i, node) in self.nodes.iter().enumerate() {
+                let next = node.instruction_pointer;
+                if node.instruction_len > 0 && self.breakpoints[i][next as usize] {
+                    return Status::Breakpoint {
+                        node: i as u8,
+                        instruction: next,
+                    };
+                }
+            }
+        }
+        self.cycle += 1;
+        for (i, (node, instructions)) in self
+            .nodes
+            .iter_mut()
+            .zip(self.instructions.chunks_exact(INSTRUCTIONS_PER_NODE))
+            .enumerate()
+        {
+            if self.layout[i] != NodeKind::Compute {
+                // stacks get their turn after every program has run, damaged tiles never do
+                continue;
+            }
+            node.fetch(instructions);
+            node.read_step();
+            if node.mode == Mode::Read {
+                if let Some(direction) = node.direction {
+                    for &d in resolve_any(direction) {
+                        let Some(taken) = self.ports[map_port(d, i)].take() else {
+                            continue;
+                        };
+                        node.port_read_buffer = Some(clamp(taken.value));
+                        node.last_port = Some(d);
+                        withdraw(&mut self.ports, taken.writer);
+                        if self.layout.get(taken.writer as usize) == Some(&NodeKind::Stack) {
+                            // a stack offers its top, taking it is the pop
+                            self.stacks[taken.writer as usize].pop();
+                        } else if let Some(index) = reverse_map_node(d, i) {
+                            self.clear_writes.push((index, d.reverse()));
+                        }
+                        break;
+                    }
+                }
+            }
+            node.step();
+            if let Some(Instruction::Hcf) = node.current_instruction {
+                // the rest of the cycle still runs so the plane is left consistent
+                self.halt.get_or_insert(Halt {
+                    node: i as u8,
+                    instruction: node.instruction_pointer,
+                    cycle: self.cycle,
+                });
+            }
+            if node.mode == Mode::Write {
+                if let Some(direction) = node.direction {
+                    if let Some(value) = node.port_write_buffer {
+                        let write = PortValue {
+                            value: clamp(value),
+                            writer: i as u8,
+                        };
+                        let mut offered = false;
+                        for &d in resolve_any(direction) {
+                            let index = map_port(d, i);
+                            if self.ports[index].is_some() || self.queued_writes[index].is_some() {
+                                // that neighbour is busy writing to us, we keep waiting
+                                continue;
+                            }
+                            self.queued_writes[index] = Some(write);
+                            offered = true;
+                        }
+                        if offered {
+                            node.port_write_buffer = None;
+                        }
+                    }
+                }
+            }
+        }
+        for i in 0..NODES_PER_PLANE {
+            if self.layout[i] == NodeKind::Stack {
+                self.stack_step(i);
+            }
+        }
+        self.input_step();
+        self.output_step();
+        for (i, write_maybe) in self.queued_writes.iter_mut().enumerate() {
+            if self.ports[i].is_none() {
+                self.ports[i] = write_maybe.take();
+            }
+        }
+        for (index, direction) in self.clear_writes.iter() {
+            let node = &mut self.nodes[*index as usize];
+            node.resolve_write(*direction);
+        }
+        self.clear_writes.clear();
+        if let Some(halt) = self.halt {
+            return Status::Halted(halt);
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.mode != Mode::Run || node.current_instruction.is_none() {
+                continue;
+            }
+            let next = if node.instruction_pointer < node.instruction_len {
+                node.instruction_pointer
+            } else {
+                0
+            };
+            if self.breakpoints[i][next as usize] {
+                return Status::Breakpoint {
+                    node: i as u8,
+                    instruction: next,
+                };
+            }
+        }
+        Status::Running
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn halt_and_catch_fire() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Hcf);
+        let halt = Halt {
+            node: 0,
+            instruction: 0,
+            cycle: 1,
+        };
+        assert_eq!(Status::Halted(halt), nodeplane.step());
+    }
+
+    #[test]
+    fn halt_stops_the_whole_plane() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "ADD 1").unwrap();
+        nodeplane
+            .load_node(7, "ADD 1\nADD 1\nJEZ END\nHCF\nEND:")
+            .unwrap();
+        assert_eq!(Status::Running, nodeplane.step());
+        assert_eq!(Status::Running, nodeplane.step());
+        assert_eq!(Status::Running, nodeplane.step());
+        let halt = Halt {
+            node: 7,
+            instruction: 3,
+            cycle: 4,
+        };
+        assert_eq!(Status::Halted(halt), nodeplane.step());
+        // the halting cycle itself completes
+        assert_eq!(4, nodeplane.nodes[0].acc);
+        assert_eq!(Status::Halted(halt), nodeplane.step());
+        assert_eq!(4, nodeplane.nodes[0].acc);
+        assert_eq!(4, nodeplane.cycle);
+    }
+
+    #[test]
+    fn first_node_to_halt_is_reported() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(3, "HCF").unwrap();
+        nodeplane.load_node(2, "HCF").unwrap();
+        match nodeplane.step() {
+            Status::Halted(halt) => assert_eq!(2, halt.node),
+            status => panic!("HCF did not halt: {:?}", status),
+        }
+    }
+
+    #[test]
+    fn basic_add() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Add(Src::Literal(42)));
+        node_1_instructions[1] = Some(Instruction::Add(Src::Register(Register::Acc)));
+        nodeplane.step();
+        assert_eq!(42, nodeplane.nodes[0].acc);
+        nodeplane.step();
+        assert_eq!(84, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn read_add() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Add(Src::Port(Port::True(TruePort::Right))));
+        let node_2_instructions = nodeplane.get_node_instructions_mut(1);
+        node_2_instructions[0] = Some(Instruction::Mov(
+            Src::Literal(5000),
+            Dst::Port(Port::True(TruePort::Left)),
+        ));
+        nodeplane.step();
+        nodeplane.step();
+        // out of range values are clamped on their way through the port
+        assert_eq!(999, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn add_negative() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Add(Src::Literal(-42)));
+        nodeplane.step();
+        assert_eq!(-42, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn add_saturating() {
+        let max = 999;
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Add(Src::Literal(max)));
+        node_1_instructions[1] = Some(Instruction::Add(Src::Literal(1)));
+        node_1_instructions[2] = Some(Instruction::Add(Src::Register(Register::Acc)));
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(max, nodeplane.nodes[0].acc);
+        nodeplane.step();
+        assert_eq!(max, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn sub_saturating() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Sub(Src::Literal(600)));
+        node_1_instructions[1] = Some(Instruction::Sub(Src::Literal(600)));
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(-999, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn literals_are_clamped() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Mov(
+            Src::Literal(i16::MIN),
+            Dst::Register(Register::Acc),
+        ));
+        node_1_instructions[1] = Some(Instruction::Sav);
+        node_1_instructions[2] = Some(Instruction::Add(Src::Literal(i16::MAX)));
+        node_1_instructions[3] = Some(Instruction::Add(Src::Literal(i16::MAX)));
+        nodeplane.step();
+        assert_eq!(-999, nodeplane.nodes[0].acc);
+        nodeplane.step();
+        assert_eq!(-999, nodeplane.nodes[0].bak);
+        nodeplane.step();
+        assert_eq!(0, nodeplane.nodes[0].acc);
+        nodeplane.step();
+        assert_eq!(999, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn add_instruction_wraparound() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Add(Src::Literal(1)));
+        assert_eq!(0, nodeplane.nodes[0].acc);
+        nodeplane.step();
+        assert_eq!(1, nodeplane.nodes[0].acc);
+        nodeplane.step();
+        assert_eq!(2, nodeplane.nodes[0].acc);
+        nodeplane.step();
+        assert_eq!(3, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn negate() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Add(Src::Literal(-42)));
+        node_1_instructions[1] = Some(Instruction::Neg);
+        node_1_instructions[2] = Some(Instruction::Neg);
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(42, nodeplane.nodes[0].acc);
+        nodeplane.step();
+        assert_eq!(-42, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn negate_zero() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Neg);
+        nodeplane.step();
+        assert_eq!(0, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn basic_sav() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Add(Src::Literal(42)));
+        node_1_instructions[1] = Some(Instruction::Sav);
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(42, nodeplane.nodes[0].bak);
+    }
+
+    #[test]
+    fn basic_swp() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Add(Src::Literal(42)));
+        node_1_instructions[1] = Some(Instruction::Sav);
+        node_1_instructions[2] = Some(Instruction::Mov(
+            Src::Literal(13),
+            Dst::Register(Register::Acc),
+        ));
+        node_1_instructions[3] = Some(Instruction::Swp);
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(13, nodeplane.nodes[0].bak);
+        assert_eq!(42, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn basic_port_mov() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Mov(
+            Src::Literal(42),
+            Dst::Port(Port::True(TruePort::Right)),
+        ));
+        node_1_instructions[1] = Some(Instruction::Mov(
+            Src::Port(Port::True(TruePort::Right)),
+            Dst::Register(Register::Acc),
+        ));
+        let node_2_instructions = nodeplane.get_node_instructions_mut(1);
+        node_2_instructions[0] = Some(Instruction::Mov(
+            Src::Port(Port::True(TruePort::Left)),
+            Dst::Register(Register::Acc),
+        ));
+        node_2_instructions[1] = Some(Instruction::Mov(
+            Src::Literal(13),
+            Dst::Port(Port::True(TruePort::Left)),
+        ));
+        nodeplane.step();
+        assert_eq!(Mode::Write, nodeplane.nodes[0].mode);
+        assert_eq!(Mode::Read, nodeplane.nodes[1].mode);
+        assert_eq!(0, nodeplane.nodes[1].acc);
+        assert_eq!(TruePort::Right, nodeplane.nodes[0].last_port.unwrap());
+        assert_eq!(TruePort::Left, nodeplane.nodes[1].last_port.unwrap());
+        nodeplane.step();
+        assert_eq!(Mode::Run, nodeplane.nodes[0].mode);
+        assert_eq!(Mode::Run, nodeplane.nodes[1].mode);
+        assert_eq!(42, nodeplane.nodes[1].acc);
+        nodeplane.step();
+        assert_eq!(Mode::Read, nodeplane.nodes[0].mode);
+        assert_eq!(Mode::Write, nodeplane.nodes[1].mode);
+        nodeplane.step();
+        assert_eq!(Mode::Run, nodeplane.nodes[0].mode);
+        assert_eq!(Mode::Run, nodeplane.nodes[1].mode);
+        assert_eq!(13, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn nop_then_port_mov() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Mov(
+            Src::Literal(42),
+            Dst::Port(Port::True(TruePort::Right)),
+        ));
+        let node_2_instructions = nodeplane.get_node_instructions_mut(1);
+        node_2_instructions[0] = Some(Instruction::Add(Src::Register(Register::Acc)));
+        node_2_instructions[1] = Some(Instruction::Mov(
+            Src::Port(Port::True(TruePort::Left)),
+            Dst::Register(Register::Acc),
+        ));
+        nodeplane.step();
+        assert_eq!(Mode::Write, nodeplane.nodes[0].mode);
+        assert_eq!(Mode::Run, nodeplane.nodes[1].mode);
+        nodeplane.step();
+        // note that the Read was instant
+        assert_eq!(Mode::Run, nodeplane.nodes[0].mode);
+        assert_eq!(Mode::Run, nodeplane.nodes[1].mode);
+        assert_eq!(42, nodeplane.nodes[1].acc);
+    }
+
+    #[test]
+    fn port_mov_back() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_1_instructions = nodeplane.get_node_instructions_mut(0);
+        node_1_instructions[0] = Some(Instruction::Mov(
+            Src::Literal(13),
+            Dst::Port(Port::True(TruePort::Right)),
+        ));
+        node_1_instructions[1] = Some(Instruction::Mov(
+            Src::Port(Port::True(TruePort::Right)),
+            Dst::Register(Register::Acc),
+        ));
+
+        let node_2_instructions = nodeplane.get_node_instructions_mut(1);
+        node_2_instructions[0] = Some(Instruction::Mov(
+            Src::Port(Port::True(TruePort::Left)),
+            Dst::Port(Port::True(TruePort::Left)),
+        ));
+
+        nodeplane.step();
+        assert_eq!(Mode::Write, nodeplane.nodes[0].mode);
+        assert_eq!(Mode::Read, nodeplane.nodes[1].mode);
+        nodeplane.step();
+        assert_eq!(Mode::Run, nodeplane.nodes[0].mode);
+        assert_eq!(Mode::Write, nodeplane.nodes[1].mode);
+        nodeplane.step();
+        assert_eq!(Mode::Run, nodeplane.nodes[0].mode);
+        assert_eq!(Mode::Run, nodeplane.nodes[1].mode);
+        assert_eq!(13, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn test_jro_literal_infinite_loop() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_instructions = nodeplane.get_node_instructions_mut(0);
+        node_instructions[0] = Some(Instruction::Add(Src::Literal(1)));
+        node_instructions[1] = Some(Instruction::Jro(Src::Literal(-1)));
+        node_instructions[2] = Some(Instruction::Hcf);
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(3, nodeplane.nodes[0].acc);
+        nodeplane.step();
+    }
+
+    #[test]
+    fn test_jro_acc_infinite_loop_with_bound_check() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_instructions = nodeplane.get_node_instructions_mut(0);
+        node_instructions[0] = Some(Instruction::Add(Src::Literal(-999)));
+        node_instructions[1] = Some(Instruction::Add(Src::Literal(0)));
+        node_instructions[2] = Some(Instruction::Jro(Src::Register(Register::Acc)));
+        node_instructions[3] = Some(Instruction::Hcf);
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(-999, nodeplane.nodes[0].acc);
+        nodeplane.step();
+    }
+
+    fn branch_plane(jump: Instruction, acc: i16) -> ExecutionPlane {
+        // 0: MOV acc, ACC
+        // 1: <jump> 3
+        // 2: NOP
+        // 3: NOP
+        let mut nodeplane = ExecutionPlane::new();
+        let node_instructions = nodeplane.get_node_instructions_mut(0);
+        node_instructions[0] = Some(Instruction::Mov(
+            Src::Literal(acc),
+            Dst::Register(Register::Acc),
+        ));
+        node_instructions[1] = Some(jump);
+        node_instructions[2] = Some(Instruction::Add(Src::Register(Register::Nil)));
+        node_instructions[3] = Some(Instruction::Add(Src::Register(Register::Nil)));
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane
+    }
+
+    fn assert_branches(jump: Instruction, taken: [bool; 3]) {
+        for (acc, taken) in [1, 0, -1].into_iter().zip(taken) {
+            let nodeplane = branch_plane(jump, acc);
+            let expected = if taken { 3 } else { 2 };
+            assert_eq!(
+                expected, nodeplane.nodes[0].instruction_pointer,
+                "{} with ACC={}",
+                jump, acc
+            );
+            assert_eq!(acc, nodeplane.nodes[0].acc);
+        }
+    }
+
+    #[test]
+    fn jmp_is_unconditional() {
+        assert_branches(Instruction::Jmp(3), [true, true, true]);
+    }
+
+    #[test]
+    fn jez_tests_acc() {
+        assert_branches(Instruction::Jez(3), [false, true, false]);
+    }
+
+    #[test]
+    fn jnz_tests_acc() {
+        assert_branches(Instruction::Jnz(3), [true, false, true]);
+    }
+
+    #[test]
+    fn jgz_tests_acc() {
+        assert_branches(Instruction::Jgz(3), [true, false, false]);
+    }
+
+    #[test]
+    fn jlz_tests_acc() {
+        assert_branches(Instruction::Jlz(3), [false, false, true]);
+    }
+
+    #[test]
+    fn branch_ignores_target_value() {
+        // the old implementation compared the operand itself against zero
+        let nodeplane = branch_plane(Instruction::Jez(0), 5);
+        assert_eq!(2, nodeplane.nodes[0].instruction_pointer);
+        let nodeplane = branch_plane(Instruction::Jez(0), 0);
+        assert_eq!(0, nodeplane.nodes[0].instruction_pointer);
+    }
+
+    #[test]
+    fn branch_fall_through_keeps_running() {
+        let mut nodeplane = ExecutionPlane::new();
+        let node_instructions = nodeplane.get_node_instructions_mut(0);
+        node_instructions[0] = Some(Instruction::Add(Src::Literal(1)));
+        node_instructions[1] = Some(Instruction::Jez(0));
+        node_instructions[2] = Some(Instruction::Add(Src::Literal(10)));
+        for _ in 0..6 {
+            nodeplane.step();
+        }
+        // the branch is never taken, so the program wraps as a whole
+        assert_eq!(22, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn any_read_priority() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(5, "MOV ANY, ACC\nADD ANY").unwrap();
+        nodeplane.load_node(1, "MOV 1, DOWN").unwrap();
+        nodeplane.load_node(4, "MOV 10, RIGHT").unwrap();
+        nodeplane.load_node(6, "MOV 100, LEFT").unwrap();
+        nodeplane.load_node(9, "MOV 500, UP").unwrap();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(10, nodeplane.nodes[5].acc);
+        assert_eq!(TruePort::Left, nodeplane.nodes[5].last_port.unwrap());
+        nodeplane.step();
+        assert_eq!(110, nodeplane.nodes[5].acc);
+        assert_eq!(TruePort::Right, nodeplane.nodes[5].last_port.unwrap());
+    }
+
+    #[test]
+    fn any_write_has_a_single_taker() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(5, "MOV 7, ANY\nMOV 8, ANY").unwrap();
+        nodeplane.load_node(4, "MOV RIGHT, ACC").unwrap();
+        nodeplane.load_node(6, "MOV LEFT, ACC").unwrap();
+        nodeplane.step();
+        assert_eq!(Mode::Write, nodeplane.nodes[5].mode);
+        assert!(nodeplane.nodes[5].last_port.is_none());
+        nodeplane.step();
+        assert_eq!(7, nodeplane.nodes[4].acc);
+        assert_eq!(0, nodeplane.nodes[6].acc);
+        assert_eq!(Mode::Read, nodeplane.nodes[6].mode);
+        assert_eq!(Mode::Run, nodeplane.nodes[5].mode);
+        assert_eq!(TruePort::Left, nodeplane.nodes[5].last_port.unwrap());
+        // the other offers were withdrawn
+        assert!(nodeplane.ports.iter().all(|p| p.is_none()));
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(8, nodeplane.nodes[4].acc);
+    }
+
+    #[test]
+    fn any_to_any() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "MOV 3, ANY").unwrap();
+        nodeplane.load_node(1, "MOV ANY, ACC").unwrap();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(3, nodeplane.nodes[1].acc);
+        assert_eq!(TruePort::Left, nodeplane.nodes[1].last_port.unwrap());
+        assert_eq!(TruePort::Right, nodeplane.nodes[0].last_port.unwrap());
+    }
+
+    #[test]
+    fn any_write_leaves_incoming_value_alone() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(1, "MOV 5, LEFT").unwrap();
+        nodeplane
+            .load_node(0, "NOP\nMOV 9, ANY\nMOV RIGHT, ACC")
+            .unwrap();
+        nodeplane.load_node(4, "MOV UP, ACC").unwrap();
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(9, nodeplane.nodes[4].acc);
+        assert_eq!(TruePort::Down, nodeplane.nodes[0].last_port.unwrap());
+        nodeplane.step();
+        assert_eq!(5, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn last_reads_zero_before_any_port_is_used() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane
+            .load_node(0, "MOV 5, ACC\nMOV LAST, ACC\nADD 2\nSUB LAST")
+            .unwrap();
+        nodeplane.load_node(1, "MOV 9, LEFT").unwrap();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(0, nodeplane.nodes[0].acc);
+        assert_eq!(Mode::Run, nodeplane.nodes[0].mode);
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(2, nodeplane.nodes[0].acc);
+        assert!(nodeplane.nodes[0].last_port.is_none());
+    }
+
+    #[test]
+    fn last_discards_writes_before_any_port_is_used() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "MOV 5, LAST\nADD 1").unwrap();
+        nodeplane.load_node(1, "MOV LEFT, ACC").unwrap();
+        nodeplane.step();
+        assert_eq!(Mode::Run, nodeplane.nodes[0].mode);
+        assert_eq!(1, nodeplane.nodes[0].instruction_pointer);
+        nodeplane.step();
+        assert_eq!(1, nodeplane.nodes[0].acc);
+        assert!(nodeplane.ports.iter().all(|p| p.is_none()));
+        assert_eq!(Mode::Read, nodeplane.nodes[1].mode);
+    }
+
+    #[test]
+    fn last_bounces_back_to_any_sender() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane
+            .load_node(5, "MOV ANY, ACC\nADD 1\nMOV ACC, LAST")
+            .unwrap();
+        nodeplane
+            .load_node(6, "MOV 41, LEFT\nMOV LEFT, ACC")
+            .unwrap();
+        for _ in 0..6 {
+            nodeplane.step();
+        }
+        assert_eq!(TruePort::Right, nodeplane.nodes[5].last_port.unwrap());
+        assert_eq!(42, nodeplane.nodes[6].acc);
+    }
+
+    #[test]
+    fn last_follows_any_write() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(5, "MOV 1, ANY\nMOV LAST, ACC").unwrap();
+        nodeplane.load_node(9, "MOV UP, ACC\nMOV 7, UP").unwrap();
+        for _ in 0..5 {
+            nodeplane.step();
+        }
+        assert_eq!(TruePort::Down, nodeplane.nodes[5].last_port.unwrap());
+        assert_eq!(7, nodeplane.nodes[5].acc);
+    }
+
+    #[test]
+    fn mov_any_to_last_in_one_instruction() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(5, "MOV ANY, LAST").unwrap();
+        nodeplane.load_node(9, "MOV 12, UP\nMOV UP, ACC").unwrap();
+        for _ in 0..5 {
+            nodeplane.step();
+        }
+        assert_eq!(12, nodeplane.nodes[9].acc);
+    }
+
+    #[test]
+    fn write_into_busy_port_blocks() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "MOV 1, RIGHT").unwrap();
+        nodeplane.load_node(1, "NOP\nMOV 2, LEFT").unwrap();
+        for _ in 0..10 {
+            assert_eq!(Status::Running, nodeplane.step());
+        }
+        assert_eq!(
+            vec![
+                Stall {
+                    node: 0,
+                    direction: TruePort::Right,
+                    mode: Mode::Write,
+                },
+                Stall {
+                    node: 1,
+                    direction: TruePort::Left,
+                    mode: Mode::Write,
+                },
+            ],
+            nodeplane.stalled()
+        );
+        // neither value got lost or overwritten
+        assert_eq!(1, nodeplane.ports[5].unwrap().value);
+        assert_eq!(Some(2), nodeplane.nodes[1].port_write_buffer);
+    }
+
+    #[test]
+    fn simultaneous_writes_do_not_clobber() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "MOV 1, RIGHT").unwrap();
+        nodeplane.load_node(1, "MOV 2, LEFT").unwrap();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(1, nodeplane.ports[5].unwrap().value);
+        assert_eq!(Some(2), nodeplane.nodes[1].port_write_buffer);
+        assert_eq!(2, nodeplane.stalled().len());
+    }
+
+    #[test]
+    fn pending_write_waits_for_reader() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "MOV 8, RIGHT\nADD 1").unwrap();
+        nodeplane
+            .load_node(1, "NOP\nNOP\nNOP\nNOP\nMOV LEFT, ACC\nHOLD: JMP HOLD")
+            .unwrap();
+        for _ in 0..4 {
+            nodeplane.step();
+            assert_eq!(
+                vec![Stall {
+                    node: 0,
+                    direction: TruePort::Right,
+                    mode: Mode::Write,
+                }],
+                nodeplane.stalled()
+            );
+        }
+        nodeplane.step();
+        assert_eq!(8, nodeplane.nodes[1].acc);
+        assert!(nodeplane.stalled().is_empty());
+        nodeplane.step();
+        assert_eq!(1, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn instruction_len_counts_the_program() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "ADD 1\nADD 2\nADD 3").unwrap();
+        nodeplane.step();
+        assert_eq!(3, nodeplane.nodes[0].instruction_len);
+        assert_eq!(0, nodeplane.nodes[1].instruction_len);
+        nodeplane.load_node(0, "ADD 1").unwrap();
+        nodeplane.step();
+        assert_eq!(1, nodeplane.nodes[0].instruction_len);
+    }
+
+    #[test]
+    fn empty_node_idles() {
+        let mut nodeplane = ExecutionPlane::new();
+        for _ in 0..3 {
+            assert_eq!(Status::Running, nodeplane.step());
+        }
+        let node = &nodeplane.nodes[0];
+        assert_eq!(0, node.instruction_pointer);
+        assert!(node.current_instruction.is_none());
+        assert_eq!(Mode::Run, node.mode);
+    }
+
+    #[test]
+    fn jro_clamps_to_last_instruction() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "JRO 10\nADD 1\nADD 2").unwrap();
+        nodeplane.step();
+        assert_eq!(2, nodeplane.nodes[0].instruction_pointer);
+        nodeplane.step();
+        assert_eq!(2, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn shorter_reload_wraps_early() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "ADD 1\nADD 1\nADD 1").unwrap();
+        nodeplane.step();
+        nodeplane.step();
+        // the old program is gone, ip 2 is past the end of the new one
+        nodeplane.load_node(0, "ADD 10").unwrap();
+        nodeplane.step();
+        assert_eq!(12, nodeplane.nodes[0].acc);
+    }
+
+    // operand kinds and whether they have to come through a port from node 4
+    static OPERANDS: [(&str, bool); 6] = [
+        ("2", false),
+        ("ACC", false),
+        ("NIL", false),
+        ("LEFT", true),
+        ("ANY", true),
+        ("LAST", false),
+    ];
+
+    fn operand_plane(subject: &str, operand: &str, from_port: bool) -> ExecutionPlane {
+        let mut nodeplane = ExecutionPlane::new();
+        let program = format!(
+            "MOV 5, ACC\n{}\nADD 100\nH: JMP H",
+            subject.replace("{}", operand)
+        );
+        nodeplane.load_node(5, &program).unwrap();
+        nodeplane.load_node(6, "MOV LEFT, ACC\nH: JMP H").unwrap();
+        if from_port {
+            // take our time so the subject has to block on the read
+            nodeplane
+                .load_node(4, "NOP\nNOP\nNOP\nMOV 2, RIGHT\nH: JMP H")
+                .unwrap();
+        }
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        if from_port {
+            assert_eq!(Mode::Read, nodeplane.nodes[5].mode, "{}", program);
+            assert_eq!(1, nodeplane.nodes[5].instruction_pointer, "{}", program);
+        }
+        for _ in 0..9 {
+            nodeplane.step();
+        }
+        assert!(nodeplane.nodes[5].port_read_buffer.is_none());
+        nodeplane
+    }
+
+    fn assert_operand_matrix(
+        subject: &str,
+        expected: [i16; 6],
+        observe: fn(&ExecutionPlane) -> i16,
+    ) {
+        for ((operand, from_port), expected) in OPERANDS.into_iter().zip(expected) {
+            let nodeplane = operand_plane(subject, operand, from_port);
+            assert_eq!(
+                expected,
+                observe(&nodeplane),
+                "{}",
+                subject.replace("{}", operand)
+            );
+        }
+    }
+
+    #[test]
+    fn mov_operand_matrix() {
+        assert_operand_matrix("MOV {}, ACC", [102, 105, 100, 102, 102, 100], |p| {
+            p.nodes[5].acc
+        });
+    }
+
+    #[test]
+    fn mov_to_port_operand_matrix() {
+        assert_operand_matrix("MOV {}, RIGHT", [2, 5, 0, 2, 2, 0], |p| p.nodes[6].acc);
+    }
+
+    #[test]
+    fn add_operand_matrix() {
+        assert_operand_matrix("ADD {}", [107, 110, 105, 107, 107, 105], |p| p.nodes[5].acc);
+    }
+
+    #[test]
+    fn sub_operand_matrix() {
+        assert_operand_matrix("SUB {}", [103, 100, 105, 103, 103, 105], |p| p.nodes[5].acc);
+    }
+
+    #[test]
+    fn jro_operand_matrix() {
+        // JRO 0 spins in place, anything positive skips the ADD 100
+        assert_operand_matrix("JRO {}", [3, 3, 1, 3, 3, 1], |p| {
+            p.nodes[5].instruction_pointer as i16
+        });
+    }
+
+    #[test]
+    fn port_to_port_mov_blocks_on_both_ends() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(4, "NOP\nMOV 7, RIGHT").unwrap();
+        nodeplane.load_node(5, "MOV LEFT, RIGHT\nADD 1").unwrap();
+        nodeplane
+            .load_node(6, "NOP\nNOP\nNOP\nNOP\nMOV LEFT, ACC")
+            .unwrap();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(Mode::Read, nodeplane.nodes[5].mode);
+        nodeplane.step();
+        assert_eq!(Mode::Write, nodeplane.nodes[5].mode);
+        nodeplane.step();
+        assert_eq!(Mode::Write, nodeplane.nodes[5].mode);
+        nodeplane.step();
+        assert_eq!(7, nodeplane.nodes[6].acc);
+        assert_eq!(Mode::Run, nodeplane.nodes[5].mode);
+        nodeplane.step();
+        assert_eq!(1, nodeplane.nodes[5].acc);
+    }
+
+    #[test]
+    fn consumed_reads_do_not_linger() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "MOV 3, RIGHT\nH: JMP H").unwrap();
+        nodeplane.load_node(1, "ADD LEFT").unwrap();
+        for _ in 0..10 {
+            nodeplane.step();
+        }
+        // the second ADD LEFT has nothing to read and must not reuse the 3
+        assert_eq!(3, nodeplane.nodes[1].acc);
+        assert_eq!(Mode::Read, nodeplane.nodes[1].mode);
+    }
+
+    #[test]
+    fn breakpoints_pause_before_marked_instruction() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(2, "ADD 1\nADD 1\n!ADD 10").unwrap();
+        assert_eq!(Status::Running, nodeplane.step());
+        let pause = Status::Breakpoint {
+            node: 2,
+            instruction: 2,
+        };
+        assert_eq!(pause, nodeplane.step());
+        assert_eq!(2, nodeplane.nodes[2].acc);
+        // stepping again runs the marked instruction
+        assert_eq!(Status::Running, nodeplane.step());
+        assert_eq!(12, nodeplane.nodes[2].acc);
+        assert_eq!(Status::Running, nodeplane.step());
+        assert_eq!(pause, nodeplane.step());
+
+        // a mark on the very first instruction pauses before cycle 1
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(5, "!SUB 3\nNEG").unwrap();
+        let first = Status::Breakpoint {
+            node: 5,
+            instruction: 0,
+        };
+        assert_eq!(first, nodeplane.step());
+        assert_eq!(0, nodeplane.nodes[5].acc);
+        assert_eq!(Status::Running, nodeplane.step());
+        assert_eq!(-3, nodeplane.nodes[5].acc);
+    }
+
+    #[test]
+    fn breakpoint_after_wraparound() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "!ADD 1\nADD 1").unwrap();
+        let pause = Status::Breakpoint {
+            node: 0,
+            instruction: 0,
+        };
+        assert_eq!(pause, nodeplane.step());
+        assert_eq!(0, nodeplane.cycle);
+        assert_eq!(Status::Running, nodeplane.step());
+        assert_eq!(pause, nodeplane.step());
+        assert_eq!(2, nodeplane.nodes[0].acc);
+    }
+
+    fn stack_at(index: usize) -> ExecutionPlane {
+        let mut layout = [NodeKind::Compute; NODES_PER_PLANE];
+        layout[index] = NodeKind::Stack;
+        ExecutionPlane::with_layout(layout)
+    }
+
+    #[test]
+    fn stack_pops_in_reverse_order() {
+        let mut nodeplane = stack_at(1);
+        nodeplane
+            .load_node(0, "MOV 1, RIGHT\nMOV 2, RIGHT\nMOV 3, RIGHT\nL: JMP L")
+            .unwrap();
+        for _ in 0..10 {
+            nodeplane.step();
+        }
+        assert_eq!(Some(&[1, 2, 3][..]), nodeplane.stack(1));
+        assert_eq!(None, nodeplane.stack(0));
+        nodeplane.load_node(2, "MOV LEFT, ACC").unwrap();
+        let mut seen = vec![];
+        for _ in 0..10 {
+            nodeplane.step();
+            if seen.last() != Some(&nodeplane.nodes[2].acc) {
+                seen.push(nodeplane.nodes[2].acc);
+            }
+        }
+        assert_eq!(vec![3, 2, 1], seen);
+        assert_eq!(Some(&[][..]), nodeplane.stack(1));
+        // an empty stack has nothing to offer
+        assert_eq!(Mode::Read, nodeplane.nodes[2].mode);
+    }
+
+    #[test]
+    fn stack_blocks_writers_when_full() {
+        let mut nodeplane = stack_at(5);
+        nodeplane.load_node(4, "ADD 1\nMOV ACC, RIGHT").unwrap();
+        for _ in 0..100 {
+            nodeplane.step();
+        }
+        let stack = nodeplane.stack(5).unwrap();
+        assert_eq!(STACK_CAPACITY, stack.len());
+        assert_eq!(Some(&15), stack.last());
+        assert_eq!(Mode::Write, nodeplane.nodes[4].mode);
+        // a pop makes room for exactly one more
+        nodeplane.load_node(9, "MOV UP, ACC\nL: JMP L").unwrap();
+        for _ in 0..10 {
+            nodeplane.step();
+        }
+        assert_eq!(15, nodeplane.nodes[9].acc);
+        assert_eq!(Some(&16), nodeplane.stack(5).unwrap().last());
+        assert_eq!(Mode::Write, nodeplane.nodes[4].mode);
+    }
+
+    #[test]
+    fn stack_takes_and_serves_on_the_same_ports() {
+        // one node pushes and pops through a single port
+        let mut nodeplane = stack_at(1);
+        nodeplane
+            .load_node(
+                0,
+                "MOV 4, RIGHT\nMOV 7, RIGHT\nMOV RIGHT, ACC\nADD RIGHT\nL: JMP L",
+            )
+            .unwrap();
+        for _ in 0..12 {
+            nodeplane.step();
+        }
+        assert_eq!(11, nodeplane.nodes[0].acc);
+        assert_eq!(Some(&[][..]), nodeplane.stack(1));
+    }
+
+    #[test]
+    fn only_compute_nodes_hold_code() {
+        let mut layout = [NodeKind::Compute; NODES_PER_PLANE];
+        layout[3] = NodeKind::Stack;
+        layout[5] = NodeKind::Damaged;
+        let mut nodeplane = ExecutionPlane::with_layout(layout);
+        for (node, kind) in [(3, NodeKind::Stack), (5, NodeKind::Damaged)] {
+            let errors = nodeplane.load_node(node, "NOP").err().unwrap();
+            assert_eq!(parse::ParseErrorKind::NoCode(kind), errors[0].kind);
+            assert_eq!(Some(node), errors[0].node);
+        }
+        assert!(nodeplane.sources[5].is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn no_slots_on_a_stack() {
+        stack_at(3).get_node_instructions_mut(3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn no_slots_on_a_damaged_node() {
+        let mut layout = [NodeKind::Compute; NODES_PER_PLANE];
+        layout[7] = NodeKind::Damaged;
+        ExecutionPlane::with_layout(layout).get_node_instructions_mut(7);
+    }
+
+    #[test]
+    fn damaged_nodes_block_their_neighbours() {
+        let mut layout = [NodeKind::Compute; NODES_PER_PLANE];
+        layout[5] = NodeKind::Damaged;
+        let mut nodeplane = ExecutionPlane::with_layout(layout);
+        nodeplane.load_node(4, "MOV 1, RIGHT\nADD 1").unwrap();
+        nodeplane.load_node(6, "MOV LEFT, ACC\nADD 1").unwrap();
+        nodeplane.load_node(1, "MOV ANY, ACC").unwrap();
+        for _ in 0..50 {
+            assert_eq!(Status::Running, nodeplane.step());
+        }
+        let stalls = nodeplane.stalled();
+        let modes: Vec<_> = stalls.iter().map(|s| (s.node, s.mode)).collect();
+        assert_eq!(
+            vec![(1, Mode::Read), (4, Mode::Write), (6, Mode::Read)],
+            modes
+        );
+        assert_eq!(0, nodeplane.nodes[6].acc);
+        assert_eq!(0, nodeplane.nodes[1].acc);
+    }
+}
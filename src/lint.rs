@@ -0,0 +1,474 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::parse::{label_definitions, strip_comment, tokenize};
+use super::{
+    map_port, reverse_map_node, Dst, ExecutionPlane, Instruction, NodeKind, Port, Register, Src,
+    TruePort, NODES_PER_PLANE,
+};
+
+// checks for programs that load fine but most likely do not do what was meant
+//
+// a comment holding `allow ID, ...` silences those lints on its own line, and on the
+// line below when the comment is all there is on the line
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LintId {
+    Unreachable,
+    UnusedLabel,
+    EdgePort,
+    UnreadWrite,
+    AddNil,
+    SubAcc,
+    JroOutOfRange,
+}
+
+static LINT_IDS: [LintId; 7] = [
+    LintId::Unreachable,
+    LintId::UnusedLabel,
+    LintId::EdgePort,
+    LintId::UnreadWrite,
+    LintId::AddNil,
+    LintId::SubAcc,
+    LintId::JroOutOfRange,
+];
+
+impl LintId {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Unreachable => "unreachable",
+            Self::UnusedLabel => "unused-label",
+            Self::EdgePort => "edge-port",
+            Self::UnreadWrite => "unread-write",
+            Self::AddNil => "add-nil",
+            Self::SubAcc => "sub-acc",
+            Self::JroOutOfRange => "jro-out-of-range",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        LINT_IDS
+            .into_iter()
+            .find(|id| id.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub id: LintId,
+    pub node: u8,
+    // None for lints about labels, which are not instructions
+    pub instruction: Option<u8>,
+    // 1 indexed in the node's source, None when the node was not loaded from text
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node {}, ", self.node)?;
+        match (self.line, self.instruction) {
+            (Some(line), _) => write!(f, "line {}", line)?,
+            (None, Some(instruction)) => write!(f, "instruction {}", instruction)?,
+            (None, None) => (),
+        }
+        write!(f, ": {} [{}]", self.message, self.id.name())
+    }
+}
+
+fn reads(instruction: &Instruction) -> Option<Port> {
+    match instruction {
+        Instruction::Mov(Src::Port(p), _)
+        | Instruction::Add(Src::Port(p))
+        | Instruction::Sub(Src::Port(p))
+        | Instruction::Jro(Src::Port(p)) => Some(*p),
+        _ => None,
+    }
+}
+
+fn writes(instruction: &Instruction) -> Option<Port> {
+    match instruction {
+        Instruction::Mov(_, Dst::Port(p)) => Some(*p),
+        _ => None,
+    }
+}
+
+// fixed ports only, ANY and LAST could be anything
+fn fixed(port: Option<Port>) -> Option<TruePort> {
+    match port {
+        Some(Port::True(direction)) if direction != TruePort::Any => Some(direction),
+        _ => None,
+    }
+}
+
+fn jro_target(index: usize, offset: i16) -> isize {
+    index as isize + offset as isize
+}
+
+fn reachable(program: &[Instruction]) -> Vec<bool> {
+    let len = program.len();
+    let mut reached = vec![false; len];
+    let mut pending = if len > 0 { vec![0] } else { Vec::new() };
+    while let Some(i) = pending.pop() {
+        if reached[i] {
+            continue;
+        }
+        reached[i] = true;
+        let next = (i + 1) % len;
+        // a jump past the end wraps to the top, same as fetch
+        let target = |t: u8| if (t as usize) < len { t as usize } else { 0 };
+        match program[i] {
+            Instruction::Jmp(t) => pending.push(target(t)),
+            Instruction::Jez(t)
+            | Instruction::Jnz(t)
+            | Instruction::Jgz(t)
+            | Instruction::Jlz(t) => pending.extend([target(t), next]),
+            Instruction::Jro(Src::Literal(offset)) => {
+                let target = jro_target(i, offset).clamp(0, len as isize - 1);
+                pending.push(target as usize);
+            }
+            Instruction::Jro(_) => pending.extend(0..len),
+            Instruction::Hcf => (),
+            _ => pending.push(next),
+        }
+    }
+    reached
+}
+
+// what a node was loaded from, lines and suppressions come from here
+struct Source<'a> {
+    text: &'a str,
+    // source line of each instruction
+    lines: Vec<usize>,
+}
+
+fn allows(line: &str) -> Vec<LintId> {
+    let code = strip_comment(line);
+    let comment = line[code.len()..].trim_start_matches('#').trim_start();
+    match comment.get(..5) {
+        Some(word) if word.eq_ignore_ascii_case("allow") => tokenize(&comment[5..], 0)
+            .iter()
+            .filter_map(|t| LintId::from_name(t.text))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl Source<'_> {
+    fn allowed(&self, line: usize) -> Vec<LintId> {
+        let lines: Vec<&str> = self.text.lines().collect();
+        let mut allowed = allows(lines[line - 1]);
+        if let Some(above) = line.checked_sub(2).map(|i| lines[i]) {
+            if strip_comment(above).trim().is_empty() {
+                allowed.extend(allows(above));
+            }
+        }
+        allowed
+    }
+}
+
+impl ExecutionPlane {
+    // port traffic in that direction goes nowhere when there is no live node across it
+    fn neighbour(&self, direction: TruePort, node: usize) -> Result<u8, &'static str> {
+        match reverse_map_node(direction, node) {
+            None => Err("the edge of the grid"),
+            Some(n) if self.layout[n as usize] == NodeKind::Damaged => Err("a damaged node"),
+            Some(n) => Ok(n),
+        }
+    }
+    fn source(&self, node: usize) -> Option<Source<'_>> {
+        // edited by hand since it was loaded, the lines would be lies
+        let (text, parsed) = self.live_source(node)?;
+        Some(Source {
+            text,
+            lines: parsed.lines,
+        })
+    }
+
+    pub fn lint(&self) -> Vec<Lint> {
+        let programs: Vec<Vec<Instruction>> =
+            (0..NODES_PER_PLANE).map(|i| self.program(i)).collect();
+        let mut lints = Vec::new();
+        for (node, program) in programs.iter().enumerate() {
+            let source = self.source(node);
+            // instruction index, lint and message
+            let mut found: Vec<(usize, LintId, String)> = Vec::new();
+
+            let reached = reachable(program);
+            for (i, instruction) in program.iter().enumerate() {
+                if !reached[i] {
+                    let message = format!("'{}' can never run", instruction);
+                    found.push((i, LintId::Unreachable, message));
+                }
+                match instruction {
+                    Instruction::Add(Src::Register(Register::Nil)) => {
+                        // unless it was written as the NOP it stands for
+                        let nop = source.as_ref().is_some_and(|s| {
+                            let line = s.text.lines().nth(s.lines[i] - 1).unwrap_or("");
+                            let code = strip_comment(line);
+                            let code = code.rsplit(':').next().unwrap_or(code);
+                            code.trim()
+                                .trim_start_matches('!')
+                                .trim_start()
+                                .eq_ignore_ascii_case("NOP")
+                        });
+                        if !nop {
+                            let message = "ADD NIL does nothing, write NOP".to_string();
+                            found.push((i, LintId::AddNil, message));
+                        }
+                    }
+                    Instruction::Sub(Src::Register(Register::Acc)) => {
+                        let message = "SUB ACC only ever leaves 0, write MOV 0, ACC".to_string();
+                        found.push((i, LintId::SubAcc, message));
+                    }
+                    Instruction::Jro(Src::Literal(offset)) => {
+                        let target = jro_target(i, *offset);
+                        if target < 0 || target >= program.len() as isize {
+                            let message = format!(
+                                "JRO {} from instruction {} lands outside of 0..{}, it is clamped",
+                                offset,
+                                i,
+                                program.len()
+                            );
+                            found.push((i, LintId::JroOutOfRange, message));
+                        }
+                    }
+                    _ => (),
+                }
+                if let Some(direction) = fixed(reads(instruction)) {
+                    // an input stream stands in for the missing neighbour
+                    let fed = self
+                        .inputs
+                        .get(map_port(direction, node))
+                        .is_some_and(Option::is_some);
+                    if let (Err(nothing), false) = (self.neighbour(direction, node), fed) {
+                        let message = format!("reads {}, which is {}", direction, nothing);
+                        found.push((i, LintId::EdgePort, message));
+                    }
+                }
+                let Some(direction) = fixed(writes(instruction)) else {
+                    continue;
+                };
+                // same for an output sink past the bottom edge
+                let drained = self.sink(map_port(direction, node)).is_some();
+                let neighbour = match self.neighbour(direction, node) {
+                    Ok(neighbour) => neighbour,
+                    Err(_) if drained => continue,
+                    Err(nothing) => {
+                        let message = format!("writes {}, which is {}", direction, nothing);
+                        found.push((i, LintId::EdgePort, message));
+                        continue;
+                    }
+                };
+                // LAST could be this port, so only a neighbour that cannot read it counts
+                // a stack takes everything it is given
+                let read = self.layout[neighbour as usize] == NodeKind::Stack
+                    || programs[neighbour as usize]
+                        .iter()
+                        .any(|other| match reads(other) {
+                            Some(Port::True(d)) => d == TruePort::Any || d == direction.reverse(),
+                            Some(Port::Last) => true,
+                            None => false,
+                        });
+                if !read {
+                    let message = format!(
+                        "writes {} but node {} never reads from {}",
+                        direction,
+                        neighbour,
+                        direction.reverse()
+                    );
+                    found.push((i, LintId::UnreadWrite, message));
+                }
+            }
+
+            let mut node_lints: Vec<Lint> = found
+                .into_iter()
+                .map(|(i, id, message)| Lint {
+                    id,
+                    node: node as u8,
+                    instruction: Some(i as u8),
+                    line: source.as_ref().map(|s| s.lines[i]),
+                    message,
+                })
+                .collect();
+            let Some(source) = source else {
+                lints.extend(node_lints);
+                continue;
+            };
+            let used: HashSet<String> = source
+                .text
+                .lines()
+                .filter_map(|line| {
+                    let code = strip_comment(line);
+                    let code = code.rsplit(':').next().unwrap_or(code);
+                    let tokens = tokenize(code.trim_start().trim_start_matches('!'), 0);
+                    let mnemonic = tokens.first()?.text.to_ascii_uppercase();
+                    if !mnemonic.starts_with('J') || mnemonic == "JRO" {
+                        return None;
+                    }
+                    tokens.get(1).map(|t| t.text.to_ascii_uppercase())
+                })
+                .collect();
+            for label in label_definitions(source.text) {
+                if !used.contains(&label.name) {
+                    node_lints.push(Lint {
+                        id: LintId::UnusedLabel,
+                        node: node as u8,
+                        instruction: None,
+                        line: Some(label.line),
+                        message: format!("label '{}' is never jumped to", label.name),
+                    });
+                }
+            }
+            node_lints.retain(|l| {
+                !l.line
+                    .is_some_and(|line| source.allowed(line).contains(&l.id))
+            });
+            lints.extend(node_lints);
+        }
+        lints
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lint(sources: &[(u8, &str)]) -> Vec<(LintId, u8, Option<usize>)> {
+        let mut plane = ExecutionPlane::new();
+        for (node, source) in sources {
+            plane.load_node(*node, source).unwrap();
+        }
+        plane
+            .lint()
+            .into_iter()
+            .map(|l| (l.id, l.node, l.line))
+            .collect()
+    }
+
+    #[test]
+    fn lint_jumps_past_the_end() {
+        let mut plane = ExecutionPlane::new();
+        let slots = plane.get_node_instructions_mut(2);
+        slots[0] = Some(Instruction::Jmp(5));
+        slots[1] = Some(Instruction::Neg);
+        let lints = plane.lint();
+        assert_eq!(1, lints.len());
+        assert_eq!(
+            (LintId::Unreachable, Some(1)),
+            (lints[0].id, lints[0].instruction)
+        );
+
+        let slots = plane.get_node_instructions_mut(2);
+        slots[0] = Some(Instruction::Jez(20));
+        slots[1] = Some(Instruction::Jmp(200));
+        slots[2] = Some(Instruction::Neg);
+        let lints = plane.lint();
+        assert_eq!(1, lints.len());
+        assert_eq!(
+            (LintId::Unreachable, Some(2)),
+            (lints[0].id, lints[0].instruction)
+        );
+    }
+
+    #[test]
+    fn lint_unreachable() {
+        let lints = lint(&[(5, "A: ADD 1\nJMP A\nNEG\nSWP\nB: SAV\nJRO -1")]);
+        assert_eq!(
+            vec![
+                (LintId::Unreachable, 5, Some(3)),
+                (LintId::Unreachable, 5, Some(4)),
+                (LintId::Unreachable, 5, Some(5)),
+                (LintId::Unreachable, 5, Some(6)),
+                (LintId::UnusedLabel, 5, Some(5)),
+            ],
+            lints
+        );
+        // conditional jumps fall through, and JRO with a port can go anywhere
+        assert!(lint(&[(5, "JEZ E\nNEG\nE: JRO LEFT\nHCF\nSAV")]).is_empty());
+        assert_eq!(
+            vec![(LintId::Unreachable, 5, Some(2))],
+            lint(&[(5, "HCF\nNEG")])
+        );
+    }
+
+    #[test]
+    fn lint_edge_ports_and_unread_writes() {
+        let lints = lint(&[
+            (0, "MOV UP, LEFT\nMOV 1, RIGHT\nMOV 2, DOWN"),
+            (1, "MOV LEFT, ACC"),
+        ]);
+        assert_eq!(
+            vec![
+                (LintId::EdgePort, 0, Some(1)),
+                (LintId::EdgePort, 0, Some(1)),
+                (LintId::UnreadWrite, 0, Some(3)),
+            ],
+            lints
+        );
+        // ANY and LAST on the reading side are good enough
+        assert!(lint(&[(0, "MOV 2, DOWN"), (4, "MOV ANY, ACC")]).is_empty());
+        assert!(lint(&[(0, "MOV 2, DOWN"), (4, "MOV LAST, ACC")]).is_empty());
+
+        // stacks read from every side
+        let mut layout = [crate::NodeKind::Compute; NODES_PER_PLANE];
+        layout[4] = crate::NodeKind::Stack;
+        let mut plane = ExecutionPlane::with_layout(layout);
+        plane.load_node(0, "MOV 2, DOWN").unwrap();
+        assert!(plane.lint().is_empty());
+        layout[4] = crate::NodeKind::Damaged;
+        let mut plane = ExecutionPlane::with_layout(layout);
+        plane.load_node(0, "MOV 2, DOWN\nMOV DOWN, ACC").unwrap();
+        let lints = plane.lint();
+        assert_eq!(2, lints.len());
+        assert!(lints.iter().all(|l| l.id == LintId::EdgePort));
+        assert!(lints[1].message.contains("damaged"));
+        // reading off the top edge is fine once something feeds it
+        let mut plane = ExecutionPlane::new();
+        plane.load_node(1, "MOV UP, ACC").unwrap();
+        assert_eq!(1, plane.lint().len());
+        plane.attach_input(1, vec![1]);
+        assert!(plane.lint().is_empty());
+        plane.load_node(9, "MOV 1, DOWN").unwrap();
+        assert_eq!(1, plane.lint().len());
+        plane.attach_output(28, crate::stream::Output::new());
+        assert!(plane.lint().is_empty());
+    }
+
+    #[test]
+    fn lint_no_ops_and_jro() {
+        let lints = lint(&[(5, "ADD NIL\nNOP\nSUB ACC\nJRO 2\nJRO -5")]);
+        assert_eq!(
+            vec![
+                (LintId::AddNil, 5, Some(1)),
+                (LintId::SubAcc, 5, Some(3)),
+                (LintId::JroOutOfRange, 5, Some(4)),
+                (LintId::JroOutOfRange, 5, Some(5)),
+            ],
+            lints
+        );
+    }
+
+    #[test]
+    fn lint_suppression() {
+        let source = "# allow sub-acc, unused-label\nL: SUB ACC\nSUB ACC #allow sub-acc\nSUB ACC\n";
+        assert_eq!(vec![(LintId::SubAcc, 5, Some(4))], lint(&[(5, source)]));
+        // a comment after code only covers its own line
+        let source = "SUB ACC #allow sub-acc\nSUB ACC\n";
+        assert_eq!(vec![(LintId::SubAcc, 5, Some(2))], lint(&[(5, source)]));
+    }
+
+    #[test]
+    fn lint_without_source() {
+        let mut plane = ExecutionPlane::new();
+        plane.get_node_instructions_mut(5)[0] =
+            Some(Instruction::Sub(Src::Register(Register::Acc)));
+        let lints = plane.lint();
+        assert_eq!(1, lints.len());
+        assert_eq!(None, lints[0].line);
+        assert_eq!(Some(0), lints[0].instruction);
+        assert_eq!(
+            "node 5, instruction 0: SUB ACC only ever leaves 0, write MOV 0, ACC [sub-acc]",
+            lints[0].to_string()
+        );
+    }
+}
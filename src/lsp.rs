@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use super::json::{self, Value};
+use super::parse::{
+    label_definitions, parse_program, strip_comment, tokenize, MNEMONICS, OPERANDS,
+};
+use super::save::{split_sections, SaveError, SaveErrorKind};
+use super::stream::OUTPUT_PORTS;
+use super::validate::validate_node;
+use super::{
+    compute_nodes, map_port, reverse_map_node, Dst, Instruction, Layout, NodeKind, Port, TruePort,
+};
+
+// a language server over stdio, documents are whole save files or a single node's source
+//
+// positions are taken to be in characters rather than the utf-16 units the protocol
+// asks for, which only differs once a comment has something outside the bmp
+
+const ERROR: usize = 1;
+const WARNING: usize = 2;
+
+// protocol numbers for the kinds of completion items
+const KEYWORD: usize = 14;
+const CONSTANT: usize = 21;
+const REFERENCE: usize = 18;
+
+struct NodeText<'a> {
+    // the '@N' of the section, None when the document has no headers
+    node: Option<u8>,
+    // 0 indexed document line of the first line of `source`
+    first_line: usize,
+    source: &'a str,
+}
+
+fn nodes(text: &str) -> Result<Vec<NodeText<'_>>, SaveError> {
+    if !text.lines().any(|line| line.starts_with('@')) {
+        return Ok(vec![NodeText {
+            node: None,
+            first_line: 0,
+            source: text,
+        }]);
+    }
+    Ok(split_sections(text)?
+        .into_iter()
+        .map(|section| NodeText {
+            node: Some(section.node as u8),
+            first_line: section.line,
+            source: section.source(),
+        })
+        .collect())
+}
+
+// the node whose source covers `line`, along with the line relative to that source
+fn node_at<'a>(nodes: &'a [NodeText<'a>], line: usize) -> Option<(&'a NodeText<'a>, usize)> {
+    nodes
+        .iter()
+        .rev()
+        .find(|n| n.first_line <= line)
+        .map(|n| (n, line - n.first_line))
+}
+
+fn position(line: usize, character: usize) -> Value {
+    Value::object([("line", line.into()), ("character", character.into())])
+}
+
+fn range(line: usize, character: usize, width: usize) -> Value {
+    Value::object([
+        ("start", position(line, character)),
+        ("end", position(line, character + width)),
+    ])
+}
+
+fn diagnostic(
+    line: usize,
+    character: usize,
+    width: usize,
+    severity: usize,
+    code: &str,
+    message: String,
+) -> Value {
+    Value::object([
+        ("range", range(line, character, width)),
+        ("severity", severity.into()),
+        ("code", code.into()),
+        ("source", "tis100".into()),
+        ("message", message.into()),
+    ])
+}
+
+// '@N' is mapped to a grid position through the layout the client gave in its
+// initialization options, an all compute grid without one. nothing is known about which
+// edge ports get inputs or outputs, so reads off the top and writes off the bottom are
+// taken to have one
+fn diagnostics(text: &str, layout: &Layout) -> Vec<Value> {
+    let nodes = match nodes(text) {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            let width = text
+                .lines()
+                .nth(e.line - 1)
+                .map_or(0, |l| l.chars().count());
+            let message = e.kind.to_string();
+            return vec![diagnostic(e.line - 1, 0, width, ERROR, "save", message)];
+        }
+    };
+    let mut diagnostics = Vec::new();
+    for n in &nodes {
+        if let Some(i) = n.node.filter(|&i| grid_node(layout, i).is_none()) {
+            // the header sits on the line above the source
+            let message = SaveErrorKind::NodeOutOfRange(i as usize).to_string();
+            let width = text.lines().nth(n.first_line - 1).map_or(0, str::len);
+            diagnostics.push(diagnostic(
+                n.first_line - 1,
+                0,
+                width,
+                ERROR,
+                "save",
+                message,
+            ));
+        }
+        match parse_program(n.source) {
+            Err(errors) => {
+                for e in errors {
+                    let message = match &e.suggestion {
+                        Some(suggestion) => format!("{}, {}", e.kind, suggestion),
+                        None => e.kind.to_string(),
+                    };
+                    let line = n.first_line + e.line - 1;
+                    diagnostics.push(diagnostic(
+                        line,
+                        e.column - 1,
+                        e.width,
+                        ERROR,
+                        "parse",
+                        message,
+                    ));
+                }
+            }
+            Ok(program) => {
+                let Some(node) = n.node.and_then(|i| grid_node(layout, i)) else {
+                    continue;
+                };
+                let lines: Vec<&str> = n.source.lines().collect();
+                for (instruction, &line) in program.instructions.iter().zip(&program.lines) {
+                    let Instruction::Mov(_, Dst::Port(Port::True(direction))) = instruction else {
+                        continue;
+                    };
+                    if *direction == TruePort::Any
+                        || OUTPUT_PORTS.contains(&map_port(*direction, node as usize))
+                    {
+                        continue;
+                    }
+                    let nothing = match reverse_map_node(*direction, node as usize) {
+                        None => "the edge of the grid",
+                        Some(n) if layout[n as usize] == NodeKind::Damaged => "a damaged node",
+                        Some(_) => continue,
+                    };
+                    // the destination is always the last operand
+                    let code = strip_comment(lines[line - 1]);
+                    let target = *tokenize(code, 0).last().unwrap();
+                    let message = format!(
+                        "{} of node {} is {}, nothing there reads it",
+                        direction, node, nothing
+                    );
+                    diagnostics.push(diagnostic(
+                        n.first_line + line - 1,
+                        code[..target.start].chars().count(),
+                        target.text.chars().count(),
+                        WARNING,
+                        "edge-port",
+                        message,
+                    ));
+                }
+            }
+        }
+        if let Some(node) = n.node.and_then(|i| grid_node(layout, i)) {
+            for v in validate_node(node, n.source) {
+                diagnostics.push(diagnostic(
+                    n.first_line + v.line - 1,
+                    v.column - 1,
+                    1,
+                    WARNING,
+                    "constraint",
+                    v.kind.to_string(),
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+fn word_at(line: &str, character: usize) -> Option<String> {
+    let chars: Vec<char> = line.chars().collect();
+    let is_word = |c: &char| c.is_ascii_alphanumeric() || *c == '_' || *c == '-';
+    let start = chars[..character.min(chars.len())]
+        .iter()
+        .rposition(|c| !is_word(c))
+        .map_or(0, |i| i + 1);
+    let word: String = chars[start..].iter().take_while(|c| is_word(c)).collect();
+    (!word.is_empty()).then(|| word.to_ascii_uppercase())
+}
+
+fn hover(text: &str, line: usize) -> Value {
+    let Ok(nodes) = nodes(text) else {
+        return Value::Null;
+    };
+    let Some((n, relative)) = node_at(&nodes, line) else {
+        return Value::Null;
+    };
+    let Ok(program) = parse_program(n.source) else {
+        return Value::Null;
+    };
+    let Some(index) = program.lines.iter().position(|&l| l == relative + 1) else {
+        return Value::Null;
+    };
+    let instruction = program.instructions[index];
+    let mut value = format!(
+        "```\n{}\n```\n`{:?}`, instruction {}",
+        instruction, instruction, index
+    );
+    if let Some(node) = n.node {
+        value.push_str(&format!(" of node {}", node));
+    }
+    Value::object([(
+        "contents",
+        Value::object([("kind", "markdown".into()), ("value", value.into())]),
+    )])
+}
+
+fn definition(uri: &str, text: &str, line: usize, character: usize) -> Value {
+    let Ok(nodes) = nodes(text) else {
+        return Value::Null;
+    };
+    let Some((n, relative)) = node_at(&nodes, line) else {
+        return Value::Null;
+    };
+    let Some(word) = n
+        .source
+        .lines()
+        .nth(relative)
+        .and_then(|l| word_at(l, character))
+    else {
+        return Value::Null;
+    };
+    label_definitions(n.source)
+        .into_iter()
+        .find(|label| label.name == word)
+        .map_or(Value::Null, |label| {
+            let line = n.first_line + label.line - 1;
+            Value::object([
+                ("uri", uri.into()),
+                ("range", range(line, label.column - 1, label.width)),
+            ])
+        })
+}
+
+fn completion(text: &str, line: usize) -> Value {
+    let item =
+        |label: &str, kind: usize| Value::object([("label", label.into()), ("kind", kind.into())]);
+    let mut items: Vec<Value> = MNEMONICS.iter().map(|m| item(m, KEYWORD)).collect();
+    items.extend(OPERANDS.iter().map(|o| item(o, CONSTANT)));
+    if let Ok(nodes) = nodes(text) {
+        if let Some((n, _)) = node_at(&nodes, line) {
+            items.extend(
+                label_definitions(n.source)
+                    .iter()
+                    .map(|l| item(&l.name, REFERENCE)),
+            );
+        }
+    }
+    items.into()
+}
+
+#[derive(Default)]
+pub(crate) struct Server {
+    // uri to the latest full text
+    documents: HashMap<String, String>,
+    layout: Layout,
+}
+
+fn grid_node(layout: &Layout, n: u8) -> Option<u8> {
+    compute_nodes(layout).nth(n as usize)
+}
+
+// initializationOptions.layout, twelve of "compute", "stack" or "damaged"
+fn parse_layout(options: &Value) -> Option<Layout> {
+    let kinds = options.get("layout").as_array()?;
+    let mut layout = Layout::default();
+    if kinds.len() != layout.len() {
+        return None;
+    }
+    for (slot, kind) in layout.iter_mut().zip(kinds) {
+        *slot = match kind.as_str()? {
+            "compute" => NodeKind::Compute,
+            "stack" => NodeKind::Stack,
+            "damaged" => NodeKind::Damaged,
+            _ => return None,
+        };
+    }
+    Some(layout)
+}
+
+fn response(id: &Value, result: Value) -> Value {
+    Value::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        ("result", result),
+    ])
+}
+
+fn error_response(id: &Value, code: f64, message: &str) -> Value {
+    Value::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.clone()),
+        (
+            "error",
+            Value::object([("code", Value::Number(code)), ("message", message.into())]),
+        ),
+    ])
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    Value::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Value::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+        ),
+    ])
+}
+
+impl Server {
+    // everything that has to be sent back for one incoming message
+    pub(crate) fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let id = message.get("id");
+        let params = message.get("params");
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or("");
+        let line = params.get("position").get("line").as_u64().unwrap_or(0) as usize;
+        let character = params
+            .get("position")
+            .get("character")
+            .as_u64()
+            .unwrap_or(0) as usize;
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let method = message.get("method").as_str().unwrap_or("");
+        let result = match method {
+            "initialize" => {
+                if let Some(layout) = parse_layout(params.get("initializationOptions")) {
+                    self.layout = layout;
+                }
+                Value::object([
+                    (
+                        "capabilities",
+                        Value::object([
+                            // full text on every change, node sources are tiny
+                            ("textDocumentSync", Value::Number(1.0)),
+                            ("hoverProvider", true.into()),
+                            ("definitionProvider", true.into()),
+                            ("completionProvider", Value::object([])),
+                        ]),
+                    ),
+                    ("serverInfo", Value::object([("name", "tis100-vm".into())])),
+                ])
+            }
+            "textDocument/hover" => hover(text, line),
+            "textDocument/definition" => definition(uri, text, line, character),
+            "textDocument/completion" => completion(text, line),
+            "shutdown" => Value::Null,
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = match method {
+                    "textDocument/didOpen" => params.get("textDocument").get("text"),
+                    _ => params
+                        .get("contentChanges")
+                        .as_array()
+                        .and_then(|changes| changes.last())
+                        .map_or(&Value::Null, |change| change.get("text")),
+                };
+                let text = text.as_str().unwrap_or("").to_string();
+                let diagnostics = diagnostics(&text, &self.layout);
+                self.documents.insert(uri.to_string(), text);
+                return vec![publish(uri, diagnostics)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish(uri, Vec::new())];
+            }
+            // notifications need no answer, unknown requests do
+            _ if id.is_null() => return Vec::new(),
+            _ => return vec![error_response(id, -32601.0, "method not found")],
+        };
+        vec![response(id, result)]
+    }
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a Content-Length",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+// runs until the client sends 'exit' or closes the stream
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some(body) = read_message(&mut input)? {
+        let message = match json::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let reply = error_response(&Value::Null, -32700.0, &e.to_string());
+                write_message(&mut output, &reply)?;
+                continue;
+            }
+        };
+        if message.get("method").as_str() == Some("exit") {
+            break;
+        }
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::NODES_PER_PLANE;
+
+    const URI: &str = "file:///solution.txt";
+
+    fn request(method: &str, params: Value) -> Value {
+        Value::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", Value::Number(1.0)),
+            ("method", method.into()),
+            ("params", params),
+        ])
+    }
+
+    fn at(line: usize, character: usize) -> Value {
+        Value::object([
+            ("textDocument", Value::object([("uri", URI.into())])),
+            ("position", position(line, character)),
+        ])
+    }
+
+    fn open(server: &mut Server, text: &str) -> Vec<Value> {
+        let document = Value::object([("uri", URI.into()), ("text", text.into())]);
+        let replies = server.handle(&request(
+            "textDocument/didOpen",
+            Value::object([("textDocument", document)]),
+        ));
+        replies[0]
+            .get("params")
+            .get("diagnostics")
+            .as_array()
+            .unwrap()
+            .to_vec()
+    }
+
+    fn start(diagnostic: &Value) -> (u64, u64) {
+        let start = diagnostic.get("range").get("start");
+        (
+            start.get("line").as_u64().unwrap(),
+            start.get("character").as_u64().unwrap(),
+        )
+    }
+
+    #[test]
+    fn lsp_edges_follow_the_layout() {
+        let mut server = Server::default();
+        // outputs hang off the bottom row
+        assert!(open(&mut server, "@8\nMOV 1, DOWN\n").is_empty());
+
+        let mut kinds: Vec<Value> = vec!["compute".into(); NODES_PER_PLANE];
+        kinds[0] = "stack".into();
+        kinds[5] = "damaged".into();
+        let options = Value::object([("layout", kinds.into())]);
+        server.handle(&request(
+            "initialize",
+            Value::object([("initializationOptions", options)]),
+        ));
+        // '@0' is grid node 1 now, which has node 5 below it
+        let diagnostics = open(&mut server, "@0\nMOV 1, UP\nMOV 1, DOWN\nMOV 1, LEFT\n");
+        let messages: Vec<&str> = diagnostics
+            .iter()
+            .map(|d| d.get("message").as_str().unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                "UP of node 1 is the edge of the grid, nothing there reads it",
+                "DOWN of node 1 is a damaged node, nothing there reads it",
+            ],
+            messages
+        );
+        let diagnostics = open(&mut server, "@9\n\n@10\n");
+        assert_eq!(1, diagnostics.len());
+        assert_eq!((2, 0), start(&diagnostics[0]));
+        assert_eq!(Some("save"), diagnostics[0].get("code").as_str());
+    }
+
+    #[test]
+    fn lsp_diagnostics() {
+        let mut server = Server::default();
+        let diagnostics = open(
+            &mut server,
+            "@0\nMOV 1, UP\nMOV 1, DOWN\n\n@1\nADD 1\n  FOO\n\n",
+        );
+        assert_eq!(2, diagnostics.len());
+        // node 0 has nothing above it
+        assert_eq!((1, 7), start(&diagnostics[0]));
+        assert_eq!(Some("edge-port"), diagnostics[0].get("code").as_str());
+        assert_eq!(
+            Some(WARNING as u64),
+            diagnostics[0].get("severity").as_u64()
+        );
+        assert_eq!((6, 2), start(&diagnostics[1]));
+        assert_eq!(Some(ERROR as u64), diagnostics[1].get("severity").as_u64());
+
+        // plain sources are a single node, so no edges
+        assert!(open(&mut server, "MOV 1, UP\nMOV UP, ACC").is_empty());
+        let diagnostics = open(&mut server, "MOV 1, UP\nMOV UP, ACX");
+        assert_eq!((1, 8), start(&diagnostics[0]));
+        assert!(diagnostics[0]
+            .get("message")
+            .as_str()
+            .unwrap()
+            .contains("did you mean 'ACC'?"));
+
+        let diagnostics = open(&mut server, "@0\nMOV 1, RIGHT # well over the limit\n");
+        assert_eq!(Some("constraint"), diagnostics[0].get("code").as_str());
+        assert_eq!((1, 18), start(&diagnostics[0]));
+    }
+
+    #[test]
+    fn lsp_hover_definition_completion() {
+        let mut server = Server::default();
+        open(
+            &mut server,
+            "@0\n\n@1\nSTART: MOV UP, ACC\n!loop: JGZ start\nJMP LOOP\n",
+        );
+        let hover = server.handle(&request("textDocument/hover", at(4, 9)));
+        let value = hover[0].get("result").get("contents").get("value");
+        assert_eq!(
+            Some("```\nJGZ L0\n```\n`Jgz(0)`, instruction 1 of node 1"),
+            value.as_str()
+        );
+        let nothing = server.handle(&request("textDocument/hover", at(1, 0)));
+        assert!(nothing[0].get("result").is_null());
+
+        let definition = server.handle(&request("textDocument/definition", at(5, 6)));
+        let range = definition[0].get("result").get("range");
+        assert_eq!(Some(4), range.get("start").get("line").as_u64());
+        assert_eq!(Some(1), range.get("start").get("character").as_u64());
+        assert_eq!(Some(5), range.get("end").get("character").as_u64());
+
+        let completion = server.handle(&request("textDocument/completion", at(5, 0)));
+        let labels: Vec<&str> = completion[0]
+            .get("result")
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|item| item.get("label").as_str())
+            .collect();
+        for expected in ["MOV", "JRO", "LAST", "ACC", "START", "LOOP"] {
+            assert!(labels.contains(&expected), "{}", expected);
+        }
+    }
+
+    #[test]
+    fn lsp_over_stdio() {
+        let messages = [
+            request("initialize", Value::object([])),
+            request("textDocument/unknown", Value::object([])),
+            request("shutdown", Value::Null),
+            Value::object([("jsonrpc", "2.0".into()), ("method", "exit".into())]),
+            request("initialize", Value::object([])),
+        ];
+        let mut input = String::new();
+        for message in messages {
+            let body = message.to_string();
+            input.push_str(&format!("Content-Length: {}\r\n\r\n{}", body.len(), body));
+        }
+        let mut output = Vec::new();
+        serve(io::Cursor::new(input), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let mut cursor = io::Cursor::new(output.as_bytes());
+        let mut replies = Vec::new();
+        while let Some(body) = read_message(&mut cursor).unwrap() {
+            replies.push(json::parse(&body).unwrap());
+        }
+        // nothing after exit is answered
+        assert_eq!(3, replies.len());
+        let capabilities = replies[0].get("result").get("capabilities");
+        assert_eq!(&Value::Bool(true), capabilities.get("hoverProvider"));
+        assert_eq!(
+            &Value::Number(-32601.0),
+            replies[1].get("error").get("code")
+        );
+        assert!(replies[2].get("result").is_null());
+    }
+}
@@ -0,0 +1,842 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{
+    Dst, Instruction, NodeKind, Port, Register, Src, TruePort, INSTRUCTIONS_PER_NODE, MAX_VALUE,
+    MIN_VALUE,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    UnknownMnemonic(String),
+    UnknownOperand(String),
+    LiteralOutOfRange(String),
+    InvalidDestination(String),
+    ExpectedLabel(String),
+    OperandCount {
+        mnemonic: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    TooManyInstructions(usize),
+    InvalidLabel(String),
+    DuplicateLabel(String),
+    UndefinedLabel(String),
+    // the layout puts something other than a compute node there
+    NoCode(NodeKind),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    // the parser only sees one node's source, callers fill this in
+    // always the grid index, which is not the '@N' of a save once a layout has stacks
+    // or damaged nodes
+    pub node: Option<u8>,
+    // 1 indexed, like an editor would show them
+    pub line: usize,
+    pub column: usize,
+    // how many characters get underlined
+    pub width: usize,
+    pub kind: ParseErrorKind,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownMnemonic(m) => write!(f, "unknown instruction '{}'", m),
+            Self::UnknownOperand(o) => write!(f, "invalid operand '{}'", o),
+            Self::LiteralOutOfRange(o) => {
+                write!(f, "'{}' is outside of {}..={}", o, MIN_VALUE, MAX_VALUE)
+            }
+            Self::InvalidDestination(o) => write!(f, "'{}' cannot be written to", o),
+            Self::ExpectedLabel(o) => write!(f, "expected a label, found '{}'", o),
+            Self::OperandCount {
+                mnemonic,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} takes {} operand(s), found {}",
+                mnemonic, expected, found
+            ),
+            Self::TooManyInstructions(n) => write!(
+                f,
+                "{} instructions do not fit in a node ({} max)",
+                n, INSTRUCTIONS_PER_NODE
+            ),
+            Self::InvalidLabel(l) => write!(f, "invalid label '{}'", l),
+            Self::DuplicateLabel(l) => write!(f, "label '{}' is already defined", l),
+            Self::UndefinedLabel(l) => write!(f, "undefined label '{}'", l),
+            Self::NoCode(kind) => write!(
+                f,
+                "{} holds no code",
+                match kind {
+                    NodeKind::Compute => "this node",
+                    NodeKind::Stack => "a stack node",
+                    NodeKind::Damaged => "a damaged node",
+                }
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(node) = self.node {
+            write!(f, "node {}, ", node)?;
+        }
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// compiler style, the offending line quoted and the problem underlined
+pub fn render_report(source: &str, errors: &[ParseError]) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let gutter = errors
+        .iter()
+        .map(|e| e.line.to_string().len())
+        .max()
+        .unwrap_or(1);
+    let mut report = String::new();
+    for error in errors {
+        let text = lines.get(error.line - 1).copied().unwrap_or("");
+        let location = match error.node {
+            Some(node) => format!(
+                "node {}, line {}, column {}",
+                node, error.line, error.column
+            ),
+            None => format!("line {}, column {}", error.line, error.column),
+        };
+        report.push_str(&format!("error: {}\n", error.kind));
+        report.push_str(&format!("{:>w$}--> {}\n", "", location, w = gutter));
+        report.push_str(&format!("{:>w$} |\n", "", w = gutter));
+        report.push_str(&format!("{:>w$} | {}\n", error.line, text, w = gutter));
+        report.push_str(&format!(
+            "{:>w$} | {}{}",
+            "",
+            " ".repeat(error.column - 1),
+            "^".repeat(error.width.max(1)),
+            w = gutter
+        ));
+        if let Some(suggestion) = &error.suggestion {
+            report.push(' ');
+            report.push_str(suggestion);
+        }
+        report.push_str("\n\n");
+    }
+    report
+}
+
+pub(crate) static MNEMONICS: [&str; 14] = [
+    "MOV", "ADD", "SUB", "JRO", "JMP", "JEZ", "JNZ", "JGZ", "JLZ", "NOP", "SAV", "SWP", "NEG",
+    "HCF",
+];
+
+pub(crate) static OPERANDS: [&str; 8] =
+    ["ACC", "NIL", "UP", "DOWN", "LEFT", "RIGHT", "ANY", "LAST"];
+
+fn usage(mnemonic: &str) -> &'static str {
+    match mnemonic {
+        "MOV" => "MOV <SRC>, <DST>",
+        "ADD" => "ADD <SRC>",
+        "SUB" => "SUB <SRC>",
+        "JRO" => "JRO <SRC>",
+        "JMP" => "JMP <LABEL>",
+        "JEZ" => "JEZ <LABEL>",
+        "JNZ" => "JNZ <LABEL>",
+        "JGZ" => "JGZ <LABEL>",
+        "JLZ" => "JLZ <LABEL>",
+        "NOP" => "NOP",
+        "SAV" => "SAV",
+        "SWP" => "SWP",
+        "NEG" => "NEG",
+        _ => "HCF",
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+pub(crate) fn closest<'a>(
+    word: &str,
+    candidates: impl Iterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let word = word.to_ascii_uppercase();
+    candidates
+        .map(|c| (edit_distance(&word, c), c))
+        .filter(|&(distance, _)| distance <= 2 && distance < word.len())
+        .min()
+        .map(|(_, c)| c)
+}
+
+fn suggest(kind: &ParseErrorKind, labels: &HashMap<String, u8>) -> Option<String> {
+    let did_you_mean = |c: &str| format!("did you mean '{}'?", c);
+    match kind {
+        ParseErrorKind::UnknownMnemonic(m) => closest(m, MNEMONICS.into_iter()).map(did_you_mean),
+        ParseErrorKind::UnknownOperand(o) => Some(
+            closest(o, OPERANDS.into_iter())
+                .map(did_you_mean)
+                .unwrap_or_else(|| "expected ACC, NIL, a port or a number".to_string()),
+        ),
+        ParseErrorKind::LiteralOutOfRange(_) => {
+            Some(format!("values range from {} to {}", MIN_VALUE, MAX_VALUE))
+        }
+        ParseErrorKind::InvalidDestination(_) => {
+            Some("only ACC, NIL or a port can be written to".to_string())
+        }
+        ParseErrorKind::ExpectedLabel(_) => {
+            Some("jumps take a label, JRO does relative jumps".to_string())
+        }
+        ParseErrorKind::OperandCount { mnemonic, .. } => {
+            Some(format!("usage: {}", usage(mnemonic)))
+        }
+        ParseErrorKind::InvalidLabel(_) => {
+            Some("labels are made of letters, digits, '_' and '-'".to_string())
+        }
+        ParseErrorKind::UndefinedLabel(l) => Some(
+            closest(l, labels.keys().map(String::as_str))
+                .map(did_you_mean)
+                .unwrap_or_else(|| format!("define it with '{}:'", l)),
+        ),
+        ParseErrorKind::TooManyInstructions(_)
+        | ParseErrorKind::DuplicateLabel(_)
+        | ParseErrorKind::NoCode(_) => None,
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Token<'a> {
+    pub text: &'a str,
+    // byte offset into the line
+    pub start: usize,
+}
+
+struct Statement<'a> {
+    number: usize,
+    line: &'a str,
+    tokens: Vec<Token<'a>>,
+}
+
+impl Statement<'_> {
+    fn error(&self, first: Token, last: Token, kind: ParseErrorKind) -> ParseError {
+        make_error(
+            self.number,
+            self.line,
+            first.start,
+            last.start + last.text.len(),
+            kind,
+        )
+    }
+    // parse one operand, on failure note the error and keep going with the rest
+    fn check<T>(
+        &self,
+        errors: &mut Vec<ParseError>,
+        token: Token,
+        parse: impl Fn(&str) -> Result<T, ParseErrorKind>,
+    ) -> Option<T> {
+        parse(token.text)
+            .map_err(|kind| errors.push(self.error(token, token, kind)))
+            .ok()
+    }
+}
+
+fn make_error(
+    number: usize,
+    line: &str,
+    start: usize,
+    end: usize,
+    kind: ParseErrorKind,
+) -> ParseError {
+    ParseError {
+        node: None,
+        line: number,
+        column: line[..start].chars().count() + 1,
+        width: line[start..end].chars().count(),
+        kind,
+        suggestion: None,
+    }
+}
+
+pub(crate) fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
+pub(crate) fn tokenize(text: &str, offset: usize) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        let separator = c == ',' || c.is_whitespace();
+        match (start, separator) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                tokens.push(Token {
+                    text: &text[s..i],
+                    start: offset + s,
+                });
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    tokens
+}
+
+fn parse_port(token: &str) -> Option<Port> {
+    Some(match token {
+        "UP" => Port::True(TruePort::Up),
+        "DOWN" => Port::True(TruePort::Down),
+        "LEFT" => Port::True(TruePort::Left),
+        "RIGHT" => Port::True(TruePort::Right),
+        "ANY" => Port::True(TruePort::Any),
+        "LAST" => Port::Last,
+        _ => return None,
+    })
+}
+
+fn parse_register(token: &str) -> Option<Register> {
+    match token {
+        "ACC" => Some(Register::Acc),
+        "NIL" => Some(Register::Nil),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_src(token: &str) -> Result<Src, ParseErrorKind> {
+    let upper = token.to_ascii_uppercase();
+    if let Some(port) = parse_port(&upper) {
+        return Ok(Src::Port(port));
+    }
+    if let Some(register) = parse_register(&upper) {
+        return Ok(Src::Register(register));
+    }
+    // anything numeric is a literal, even when it is too large to be one
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        return match token.parse::<i16>() {
+            Ok(value) if (MIN_VALUE..=MAX_VALUE).contains(&value) => Ok(Src::Literal(value)),
+            _ => Err(ParseErrorKind::LiteralOutOfRange(token.to_string())),
+        };
+    }
+    Err(ParseErrorKind::UnknownOperand(token.to_string()))
+}
+
+fn parse_dst(token: &str) -> Result<Dst, ParseErrorKind> {
+    match parse_src(token)? {
+        Src::Port(port) => Ok(Dst::Port(port)),
+        Src::Register(register) => Ok(Dst::Register(register)),
+        Src::Literal(_) => Err(ParseErrorKind::InvalidDestination(token.to_string())),
+    }
+}
+
+fn parse_label(token: &str, labels: &HashMap<String, u8>) -> Result<u8, ParseErrorKind> {
+    if let Some(&target) = labels.get(&token.to_ascii_uppercase()) {
+        return Ok(target);
+    }
+    match parse_src(token) {
+        // a label is allowed to shadow nothing, so a known operand here is a mistake
+        Ok(_) | Err(ParseErrorKind::LiteralOutOfRange(_)) => {
+            Err(ParseErrorKind::ExpectedLabel(token.to_string()))
+        }
+        Err(_) => Err(ParseErrorKind::UndefinedLabel(token.to_string())),
+    }
+}
+
+fn arity(mnemonic: &str) -> usize {
+    match mnemonic {
+        "MOV" => 2,
+        "ADD" | "SUB" | "JRO" | "JMP" | "JEZ" | "JNZ" | "JGZ" | "JLZ" => 1,
+        _ => 0,
+    }
+}
+
+fn parse_instruction(
+    statement: &Statement,
+    labels: &HashMap<String, u8>,
+) -> Result<Instruction, Vec<ParseError>> {
+    let mnemonic_token = statement.tokens[0];
+    let operands = &statement.tokens[1..];
+    let upper = mnemonic_token.text.to_ascii_uppercase();
+    let Some(mnemonic) = MNEMONICS.into_iter().find(|m| *m == upper) else {
+        let kind = ParseErrorKind::UnknownMnemonic(mnemonic_token.text.to_string());
+        return Err(vec![statement.error(mnemonic_token, mnemonic_token, kind)]);
+    };
+    let expected = arity(mnemonic);
+    if operands.len() != expected {
+        let kind = ParseErrorKind::OperandCount {
+            mnemonic,
+            expected,
+            found: operands.len(),
+        };
+        // point at whatever is extra, or at the instruction when something is missing
+        let (first, last) = match operands.get(expected..) {
+            Some([first, .., last]) => (*first, *last),
+            Some([only]) => (*only, *only),
+            _ => (mnemonic_token, statement.tokens[statement.tokens.len() - 1]),
+        };
+        return Err(vec![statement.error(first, last, kind)]);
+    }
+    let mut errors = Vec::new();
+    let mut src = |i: usize| statement.check(&mut errors, operands[i], parse_src);
+    let instruction = match mnemonic {
+        "MOV" => {
+            let s = src(0);
+            let d = statement.check(&mut errors, operands[1], parse_dst);
+            s.zip(d).map(|(s, d)| Instruction::Mov(s, d))
+        }
+        "ADD" => src(0).map(Instruction::Add),
+        "SUB" => src(0).map(Instruction::Sub),
+        "JRO" => src(0).map(Instruction::Jro),
+        "NOP" => Some(Instruction::Add(Src::Register(Register::Nil))),
+        "SAV" => Some(Instruction::Sav),
+        "SWP" => Some(Instruction::Swp),
+        "NEG" => Some(Instruction::Neg),
+        "HCF" => Some(Instruction::Hcf),
+        jump => {
+            let target = statement.check(&mut errors, operands[0], |t| parse_label(t, labels));
+            target.map(match jump {
+                "JMP" => Instruction::Jmp,
+                "JEZ" => Instruction::Jez,
+                "JNZ" => Instruction::Jnz,
+                "JGZ" => Instruction::Jgz,
+                _ => Instruction::Jlz,
+            })
+        }
+    };
+    match instruction {
+        Some(instruction) if errors.is_empty() => Ok(instruction),
+        _ => Err(errors),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    // indices of instructions marked with a leading '!'
+    pub breakpoints: Vec<u8>,
+    // 1 indexed source line of each instruction
+    pub lines: Vec<usize>,
+}
+
+pub fn parse_node(source: &str) -> Result<Vec<Instruction>, Vec<ParseError>> {
+    parse_program(source).map(|program| program.instructions)
+}
+
+pub fn parse_program(source: &str) -> Result<Program, Vec<ParseError>> {
+    let mut errors = Vec::new();
+    // first pass: bind labels to the index of the instruction that follows them
+    let mut labels = HashMap::new();
+    let mut breakpoints = Vec::new();
+    let mut statements = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let number = i + 1;
+        let mut rest = strip_comment(line);
+        let mut offset = 0;
+        if let Some(unmarked) = rest.trim_start().strip_prefix('!') {
+            // like a label, the breakpoint belongs to the next instruction
+            breakpoints.push(statements.len() as u8);
+            offset += rest.len() - unmarked.len();
+            rest = unmarked;
+        }
+        while let Some(colon) = rest.find(':') {
+            let label = rest[..colon].trim();
+            let start = offset + rest[..colon].len() - rest[..colon].trim_start().len();
+            let end = if label.is_empty() {
+                // nothing to underline but the colon itself
+                offset + colon + 1
+            } else {
+                start + label.len()
+            };
+            if !valid_label(label) {
+                let kind = ParseErrorKind::InvalidLabel(label.to_string());
+                errors.push(make_error(number, line, start, end, kind));
+            } else if labels
+                .insert(label.to_ascii_uppercase(), statements.len() as u8)
+                .is_some()
+            {
+                let kind = ParseErrorKind::DuplicateLabel(label.to_string());
+                errors.push(make_error(number, line, start, end, kind));
+            }
+            offset += colon + 1;
+            rest = &rest[colon + 1..];
+        }
+        let tokens = tokenize(rest, offset);
+        if tokens.is_empty() {
+            continue;
+        }
+        statements.push(Statement {
+            number,
+            line,
+            tokens,
+        });
+    }
+    if let Some(statement) = statements.get(INSTRUCTIONS_PER_NODE) {
+        let kind = ParseErrorKind::TooManyInstructions(statements.len());
+        let first = statement.tokens[0];
+        let last = statement.tokens[statement.tokens.len() - 1];
+        errors.push(statement.error(first, last, kind));
+    }
+    // a trailing label wraps around to the top, just like running off the end does
+    for index in labels.values_mut() {
+        if *index as usize == statements.len() {
+            *index = 0;
+        }
+    }
+    breakpoints.retain(|&index| (index as usize) < statements.len());
+    breakpoints.dedup();
+    // second pass: everything is known, assemble
+    let mut instructions = Vec::new();
+    for statement in statements.iter().take(INSTRUCTIONS_PER_NODE) {
+        match parse_instruction(statement, &labels) {
+            Ok(instruction) => instructions.push(instruction),
+            Err(e) => errors.extend(e),
+        }
+    }
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| (e.line, e.column));
+        for error in errors.iter_mut() {
+            error.suggestion = suggest(&error.kind, &labels);
+        }
+        return Err(errors);
+    }
+    Ok(Program {
+        instructions,
+        breakpoints,
+        lines: statements.iter().map(|s| s.number).collect(),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LabelDefinition {
+    // uppercase, the way jumps refer to it
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    pub width: usize,
+}
+
+// where each label is written, for tools that point at them rather than jump to them
+pub fn label_definitions(source: &str) -> Vec<LabelDefinition> {
+    let mut labels = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let code = strip_comment(line);
+        let mut offset = 0;
+        while let Some(colon) = code[offset..].find(':') {
+            let text = code[offset..offset + colon].trim_start();
+            let text = text.strip_prefix('!').unwrap_or(text).trim();
+            let start = offset + code[offset..offset + colon].find(text).unwrap_or(0);
+            labels.push(LabelDefinition {
+                name: text.to_ascii_uppercase(),
+                line: i + 1,
+                column: code[..start].chars().count() + 1,
+                width: text.chars().count(),
+            });
+            offset += colon + 1;
+        }
+    }
+    labels
+}
+
+fn valid_label(label: &str) -> bool {
+    !label.is_empty()
+        && label
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ExecutionPlane, Plane};
+
+    fn first_error(source: &str) -> ParseError {
+        parse_node(source).unwrap_err().remove(0)
+    }
+
+    #[test]
+    fn parse_mov_and_arithmetic() {
+        let program = parse_node("MOV 42, RIGHT\nadd acc\nSUB LEFT\nJRO -1\n").unwrap();
+        assert_eq!(4, program.len());
+        assert!(matches!(
+            program[0],
+            Instruction::Mov(Src::Literal(42), Dst::Port(Port::True(TruePort::Right)))
+        ));
+        assert!(matches!(
+            program[1],
+            Instruction::Add(Src::Register(Register::Acc))
+        ));
+        assert!(matches!(
+            program[2],
+            Instruction::Sub(Src::Port(Port::True(TruePort::Left)))
+        ));
+        assert!(matches!(program[3], Instruction::Jro(Src::Literal(-1))));
+    }
+
+    #[test]
+    fn parse_operand_separators() {
+        for source in ["MOV UP DOWN", "MOV UP,DOWN", "MOV  UP , DOWN"] {
+            let program = parse_node(source).unwrap();
+            assert!(matches!(
+                program[0],
+                Instruction::Mov(
+                    Src::Port(Port::True(TruePort::Up)),
+                    Dst::Port(Port::True(TruePort::Down))
+                )
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_skips_comments_and_blank_lines() {
+        let source = "# header\n\nSAV # keep it\n   \nSWP\nNEG\nHCF\nMOV ANY, LAST\n";
+        let program = parse_node(source).unwrap();
+        assert_eq!(5, program.len());
+        assert!(matches!(program[0], Instruction::Sav));
+        assert!(matches!(
+            program[4],
+            Instruction::Mov(Src::Port(Port::True(TruePort::Any)), Dst::Port(Port::Last))
+        ));
+    }
+
+    #[test]
+    fn parse_nop_is_add_nil() {
+        let program = parse_node("NOP").unwrap();
+        assert!(matches!(
+            program[0],
+            Instruction::Add(Src::Register(Register::Nil))
+        ));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = first_error("SAV\nFOO 1");
+        assert_eq!(2, err.line);
+        assert_eq!(ParseErrorKind::UnknownMnemonic("FOO".to_string()), err.kind);
+
+        let err = first_error("MOV 1, 2");
+        assert_eq!(
+            ParseErrorKind::InvalidDestination("2".to_string()),
+            err.kind
+        );
+
+        let err = first_error("ADD 1000");
+        assert_eq!(
+            ParseErrorKind::LiteralOutOfRange("1000".to_string()),
+            err.kind
+        );
+
+        let err = first_error("JRO -99999");
+        assert_eq!(
+            ParseErrorKind::LiteralOutOfRange("-99999".to_string()),
+            err.kind
+        );
+
+        let err = first_error("ADD BAK");
+        assert_eq!(ParseErrorKind::UnknownOperand("BAK".to_string()), err.kind);
+
+        let err = first_error("MOV ACC");
+        assert_eq!(
+            ParseErrorKind::OperandCount {
+                mnemonic: "MOV",
+                expected: 2,
+                found: 1
+            },
+            err.kind
+        );
+
+        let err = first_error(&"NOP\n".repeat(INSTRUCTIONS_PER_NODE + 1));
+        assert_eq!(INSTRUCTIONS_PER_NODE + 1, err.line);
+    }
+
+    #[test]
+    fn parse_labels() {
+        let source = "START: MOV UP, ACC\nloop:\nJEZ start\n  JGZ LOOP # back up\nJMP END\nJLZ loop\nJNZ START\nEND:\n";
+        let program = parse_node(source).unwrap();
+        assert_eq!(6, program.len());
+        assert!(matches!(program[1], Instruction::Jez(0)));
+        assert!(matches!(program[2], Instruction::Jgz(1)));
+        // END has nothing after it, so it wraps to the top
+        assert!(matches!(program[3], Instruction::Jmp(0)));
+        assert!(matches!(program[4], Instruction::Jlz(1)));
+        assert!(matches!(program[5], Instruction::Jnz(0)));
+    }
+
+    #[test]
+    fn parse_stacked_labels() {
+        let program = parse_node("A: B:\nNOP\nJMP A\nJMP B").unwrap();
+        assert!(matches!(program[1], Instruction::Jmp(0)));
+        assert!(matches!(program[2], Instruction::Jmp(0)));
+    }
+
+    #[test]
+    fn parse_label_errors() {
+        let err = first_error("JMP NOWHERE");
+        assert_eq!(
+            ParseErrorKind::UndefinedLabel("NOWHERE".to_string()),
+            err.kind
+        );
+
+        let err = first_error("L: NOP\nl: NOP");
+        assert_eq!(2, err.line);
+        assert_eq!(ParseErrorKind::DuplicateLabel("l".to_string()), err.kind);
+
+        let err = first_error("BAD LABEL: NOP");
+        assert_eq!(
+            ParseErrorKind::InvalidLabel("BAD LABEL".to_string()),
+            err.kind
+        );
+
+        let err = first_error(": NOP");
+        assert_eq!(ParseErrorKind::InvalidLabel("".to_string()), err.kind);
+    }
+
+    #[test]
+    fn jmp_loops_over_label() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane
+            .load_node(0, "ADD 5\nLOOP: ADD 1\nJMP LOOP")
+            .unwrap();
+        for _ in 0..7 {
+            nodeplane.step();
+        }
+        // ADD 5, then three rounds of ADD 1 / JMP
+        assert_eq!(8, nodeplane.nodes[0].acc);
+    }
+
+    #[test]
+    fn parse_breakpoints() {
+        let program =
+            parse_program("NOP\n!ADD 1\n  !L: SUB 1\n!\nJMP L\n!# nothing after").unwrap();
+        assert_eq!(4, program.instructions.len());
+        assert!(matches!(program.instructions[1], Instruction::Add(_)));
+        assert_eq!(vec![1, 2, 3], program.breakpoints);
+    }
+
+    #[test]
+    fn load_node_source() {
+        let mut nodeplane = ExecutionPlane::new();
+        nodeplane.load_node(0, "MOV 42, RIGHT").unwrap();
+        nodeplane.load_node(1, "MOV LEFT, ACC\nADD ACC").unwrap();
+        nodeplane.step();
+        nodeplane.step();
+        nodeplane.step();
+        assert_eq!(84, nodeplane.nodes[1].acc);
+    }
+
+    #[test]
+    fn parse_collects_every_error() {
+        let errors =
+            parse_node("MOB UP, ACC\nMOV 5, 6\nADD\nJEZ LOPO\nLOOP: SUB RIGTH\nMOV X, 1000")
+                .unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| (e.line, e.kind.clone())).collect();
+        assert_eq!(
+            vec![
+                (1, ParseErrorKind::UnknownMnemonic("MOB".to_string())),
+                (2, ParseErrorKind::InvalidDestination("6".to_string())),
+                (
+                    3,
+                    ParseErrorKind::OperandCount {
+                        mnemonic: "ADD",
+                        expected: 1,
+                        found: 0
+                    }
+                ),
+                (4, ParseErrorKind::UndefinedLabel("LOPO".to_string())),
+                (5, ParseErrorKind::UnknownOperand("RIGTH".to_string())),
+                (6, ParseErrorKind::UnknownOperand("X".to_string())),
+                (6, ParseErrorKind::LiteralOutOfRange("1000".to_string())),
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn parse_error_spans() {
+        let err = first_error("  MOV 5,   BAD # comment");
+        assert_eq!((1, 12, 3), (err.line, err.column, err.width));
+
+        let err = first_error("!L: ADD 1, 2, 3");
+        assert_eq!((12, 4), (err.column, err.width));
+
+        let err = first_error("NOP\n  MOV ACC");
+        assert_eq!((2, 3, 7), (err.line, err.column, err.width));
+
+        let err = first_error("A: NOP\n  a : NOP");
+        assert_eq!((2, 3, 1), (err.line, err.column, err.width));
+    }
+
+    #[test]
+    fn parse_suggestions() {
+        let suggestion = |source| first_error(source).suggestion.unwrap();
+        assert_eq!("did you mean 'MOV'?", suggestion("MOB UP, ACC"));
+        assert_eq!("did you mean 'RIGHT'?", suggestion("ADD RIGTH"));
+        assert_eq!("did you mean 'LOOP'?", suggestion("LOOP: JMP LOPO"));
+        assert_eq!("define it with 'ELSEWHERE:'", suggestion("JMP ELSEWHERE"));
+        assert_eq!("usage: MOV <SRC>, <DST>", suggestion("MOV ACC"));
+        assert_eq!("values range from -999 to 999", suggestion("ADD -1000"));
+        assert!(first_error("XYZZY").suggestion.is_none());
+    }
+
+    #[test]
+    fn parse_jump_needs_label() {
+        let err = first_error("JEZ 3");
+        assert_eq!(ParseErrorKind::ExpectedLabel("3".to_string()), err.kind);
+        let err = first_error("JMP ACC");
+        assert_eq!(ParseErrorKind::ExpectedLabel("ACC".to_string()), err.kind);
+    }
+
+    #[test]
+    fn render_compiler_style_report() {
+        let source = "NOP\nMOB UP, ACC\nMOV 1, 2";
+        let mut nodeplane = ExecutionPlane::new();
+        let errors = nodeplane.load_node(4, source).unwrap_err();
+        assert_eq!(Some(4), errors[0].node);
+        let expected = "\
+error: unknown instruction 'MOB'
+ --> node 4, line 2, column 1
+  |
+2 | MOB UP, ACC
+  | ^^^ did you mean 'MOV'?
+
+error: '2' cannot be written to
+ --> node 4, line 3, column 8
+  |
+3 | MOV 1, 2
+  |        ^ only ACC, NIL or a port can be written to
+
+";
+        assert_eq!(expected, render_report(source, &errors));
+        assert_eq!(
+            "node 4, line 2, column 1: unknown instruction 'MOB'",
+            errors[0].to_string()
+        );
+    }
+}
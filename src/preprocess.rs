@@ -0,0 +1,653 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::parse::{
+    closest, parse_program, strip_comment, tokenize, ParseErrorKind, Program, MNEMONICS,
+};
+use super::ExecutionPlane;
+
+// a layer in front of the parser, expands into plain source the game could read
+//
+//   .const NAME VALUE       every $NAME after this becomes VALUE
+//   .macro NAME a, b        the lines up to .endm, $a and $b are the arguments
+//   .endm                   and $@ is a number unique to each expansion
+//   .include "FILE"         splices FILE in here, a file is only ever included once
+//
+// a macro is called like an instruction, `NAME 1, ACC`
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpandErrorKind {
+    UnknownDirective(String),
+    Usage(&'static str),
+    InvalidName(String),
+    DuplicateConstant(String),
+    DuplicateMacro(String),
+    UndefinedName(String),
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    UnterminatedMacro(String),
+    UnexpectedEndm,
+    DirectiveInMacro(String),
+    RecursiveMacro(String),
+    Include {
+        path: String,
+        reason: String,
+    },
+    // the expanded source did not assemble
+    Parse(ParseErrorKind),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpandError {
+    pub file: String,
+    // 1 indexed, in the file as it was written rather than the expansion
+    pub line: usize,
+    pub column: usize,
+    pub width: usize,
+    pub kind: ExpandErrorKind,
+    pub suggestion: Option<String>,
+    // file and line of the outermost macro call the error came out of
+    pub expanded_from: Option<(String, usize)>,
+}
+
+impl fmt::Display for ExpandErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownDirective(d) => write!(f, "unknown directive '{}'", d),
+            Self::Usage(usage) => write!(f, "usage: {}", usage),
+            Self::InvalidName(n) => write!(f, "'{}' cannot be used as a name", n),
+            Self::DuplicateConstant(n) => write!(f, "constant '{}' is already defined", n),
+            Self::DuplicateMacro(n) => write!(f, "macro '{}' is already defined", n),
+            Self::UndefinedName(n) => write!(f, "undefined name '${}'", n),
+            Self::ArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} takes {} argument(s), found {}",
+                name, expected, found
+            ),
+            Self::UnterminatedMacro(n) => write!(f, "macro '{}' has no .endm", n),
+            Self::UnexpectedEndm => write!(f, ".endm outside of a macro"),
+            Self::DirectiveInMacro(d) => write!(f, "'.{}' is not allowed inside a macro", d),
+            Self::RecursiveMacro(n) => write!(f, "macro '{}' expands into itself", n),
+            Self::Include { path, reason } => write!(f, "cannot include '{}': {}", path, reason),
+            Self::Parse(kind) => kind.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}, line {}, column {}: {}",
+            self.file, self.line, self.column, self.kind
+        )?;
+        if let Some((file, line)) = &self.expanded_from {
+            write!(f, " (expanded from {}, line {})", file, line)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ExpandError {}
+
+// where a line of the expansion was written
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub file: String,
+    pub line: usize,
+    // column and width of the code on the original line
+    pub span: (usize, usize),
+    // false once anything was substituted, columns of the expansion no longer line up
+    pub verbatim: bool,
+    pub expanded_from: Option<(String, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expansion {
+    pub text: String,
+    // one per line of `text`
+    pub origins: Vec<Origin>,
+}
+
+pub type Loader<'a> = dyn FnMut(&str) -> io::Result<String> + 'a;
+
+// includes are looked up relative to `dir`
+pub fn read_from(dir: &Path) -> impl FnMut(&str) -> io::Result<String> {
+    let dir = PathBuf::from(dir);
+    move |path| std::fs::read_to_string(dir.join(path))
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<(String, Origin)>,
+}
+
+struct Expander<'a, 'b> {
+    loader: &'a mut Loader<'b>,
+    constants: HashMap<String, String>,
+    macros: HashMap<String, Macro>,
+    included: HashSet<String>,
+    // macros being expanded right now, to catch one calling itself
+    active: Vec<String>,
+    expansions: usize,
+    lines: Vec<String>,
+    origins: Vec<Origin>,
+    errors: Vec<ExpandError>,
+}
+
+fn origin(file: &str, number: usize, line: &str) -> Origin {
+    let code = strip_comment(line);
+    let indent = code.len() - code.trim_start().len();
+    Origin {
+        file: file.to_string(),
+        line: number,
+        span: (
+            code[..indent].chars().count() + 1,
+            code.trim().chars().count(),
+        ),
+        verbatim: true,
+        expanded_from: None,
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Expander<'_, '_> {
+    fn error(&mut self, origin: &Origin, kind: ExpandErrorKind) {
+        self.error_at(origin, origin.span, kind);
+    }
+
+    fn error_at(&mut self, origin: &Origin, span: (usize, usize), kind: ExpandErrorKind) {
+        self.errors.push(ExpandError {
+            file: origin.file.clone(),
+            line: origin.line,
+            column: span.0,
+            width: span.1,
+            kind,
+            suggestion: None,
+            expanded_from: origin.expanded_from.clone(),
+        });
+    }
+
+    fn file(&mut self, name: &str, text: &str) {
+        self.included.insert(name.to_string());
+        let mut lines = text.lines().enumerate();
+        while let Some((i, line)) = lines.next() {
+            let here = origin(name, i + 1, line);
+            let code = strip_comment(line).trim();
+            let Some(directive) = code.strip_prefix('.') else {
+                self.line(line, here, &HashMap::new());
+                continue;
+            };
+            let tokens: Vec<&str> = tokenize(directive, 0).iter().map(|t| t.text).collect();
+            let keyword = tokens.first().copied().unwrap_or("");
+            match keyword.to_ascii_lowercase().as_str() {
+                "const" => self.constant(&here, &tokens[1..]),
+                "include" => self.include(&here, &tokens[1..]),
+                "endm" => self.error(&here, ExpandErrorKind::UnexpectedEndm),
+                "macro" => {
+                    let mut body = Vec::new();
+                    let mut terminated = false;
+                    for (j, line) in lines.by_ref() {
+                        let code = strip_comment(line).trim();
+                        if code.eq_ignore_ascii_case(".endm") {
+                            terminated = true;
+                            break;
+                        }
+                        let there = origin(name, j + 1, line);
+                        if let Some(directive) = code.strip_prefix('.') {
+                            let directive = directive.split_whitespace().next().unwrap_or("");
+                            let kind = ExpandErrorKind::DirectiveInMacro(directive.to_string());
+                            self.error(&there, kind);
+                            continue;
+                        }
+                        body.push((line.to_string(), there));
+                    }
+                    if !terminated {
+                        let name = tokens.get(1).copied().unwrap_or("").to_string();
+                        self.error(&here, ExpandErrorKind::UnterminatedMacro(name));
+                    }
+                    self.define(&here, &tokens[1..], body);
+                }
+                _ => {
+                    let kind = ExpandErrorKind::UnknownDirective(keyword.to_string());
+                    self.error(&here, kind);
+                }
+            }
+        }
+    }
+
+    fn constant(&mut self, here: &Origin, operands: &[&str]) {
+        let [name, value] = operands else {
+            return self.error(here, ExpandErrorKind::Usage(".const NAME VALUE"));
+        };
+        if !valid_name(name) {
+            return self.error(here, ExpandErrorKind::InvalidName(name.to_string()));
+        }
+        // constants may be built from earlier ones
+        let (value, _) = self.substitute(value, here, &HashMap::new());
+        let key = name.to_ascii_uppercase();
+        if self.constants.insert(key, value).is_some() {
+            self.error(here, ExpandErrorKind::DuplicateConstant(name.to_string()));
+        }
+    }
+
+    fn define(&mut self, here: &Origin, operands: &[&str], body: Vec<(String, Origin)>) {
+        let Some((name, params)) = operands.split_first() else {
+            return self.error(here, ExpandErrorKind::Usage(".macro NAME PARAM, ..."));
+        };
+        let key = name.to_ascii_uppercase();
+        // a macro named like an instruction would never be called
+        if !valid_name(name) || MNEMONICS.contains(&key.as_str()) {
+            return self.error(here, ExpandErrorKind::InvalidName(name.to_string()));
+        }
+        if let Some(param) = params.iter().find(|p| !valid_name(p)) {
+            return self.error(here, ExpandErrorKind::InvalidName(param.to_string()));
+        }
+        let params = params.iter().map(|p| p.to_ascii_uppercase()).collect();
+        if self.macros.insert(key, Macro { params, body }).is_some() {
+            self.error(here, ExpandErrorKind::DuplicateMacro(name.to_string()));
+        }
+    }
+
+    fn include(&mut self, here: &Origin, operands: &[&str]) {
+        let [path] = operands else {
+            return self.error(here, ExpandErrorKind::Usage(".include \"FILE\""));
+        };
+        let path = path.trim_matches('"');
+        if self.included.contains(path) {
+            return;
+        }
+        match (self.loader)(path) {
+            Ok(text) => self.file(path, &text),
+            Err(e) => {
+                let kind = ExpandErrorKind::Include {
+                    path: path.to_string(),
+                    reason: e.to_string(),
+                };
+                self.error(here, kind);
+            }
+        }
+    }
+
+    // replace every $NAME in the code part of `line`, comments are left alone
+    fn substitute(
+        &mut self,
+        line: &str,
+        here: &Origin,
+        params: &HashMap<String, String>,
+    ) -> (String, bool) {
+        let code = strip_comment(line);
+        let mut text = String::new();
+        let mut changed = false;
+        let mut rest = code;
+        while let Some(dollar) = rest.find('$') {
+            text.push_str(&rest[..dollar]);
+            let after = &rest[dollar + 1..];
+            let length = if after.starts_with('@') {
+                1
+            } else {
+                after
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(after.len())
+            };
+            let name = &after[..length];
+            let key = name.to_ascii_uppercase();
+            match params.get(&key).or_else(|| self.constants.get(&key)) {
+                Some(value) => text.push_str(value),
+                None => {
+                    let column = code[..code.len() - rest.len() + dollar].chars().count() + 1;
+                    let span = (column, length + 1);
+                    let kind = ExpandErrorKind::UndefinedName(name.to_string());
+                    self.error_at(here, span, kind);
+                    let candidates = params.keys().chain(self.constants.keys());
+                    if let Some(c) = closest(name, candidates.map(String::as_str)) {
+                        let suggestion = format!("did you mean '${}'?", c);
+                        self.errors.last_mut().unwrap().suggestion = Some(suggestion);
+                    }
+                }
+            }
+            changed = true;
+            rest = &after[length..];
+        }
+        text.push_str(rest);
+        text.push_str(&line[code.len()..]);
+        (text, changed)
+    }
+
+    fn line(&mut self, line: &str, mut here: Origin, params: &HashMap<String, String>) {
+        let (text, changed) = self.substitute(line, &here, params);
+        here.verbatim &= !changed;
+        // whatever labels and breakpoint come first stay in front of the call
+        let code = strip_comment(&text);
+        let split = match code.rfind(':') {
+            Some(colon) => colon + 1,
+            None => code.len() - code.trim_start().trim_start_matches('!').len(),
+        };
+        let tokens = tokenize(&code[split..], 0);
+        let call = tokens
+            .first()
+            .map(|t| t.text.to_ascii_uppercase())
+            .filter(|name| self.macros.contains_key(name));
+        let Some(name) = call else {
+            self.lines.push(text);
+            self.origins.push(here);
+            return;
+        };
+        if self.active.contains(&name) {
+            return self.error(
+                &here,
+                ExpandErrorKind::RecursiveMacro(tokens[0].text.into()),
+            );
+        }
+        let arguments: Vec<String> = tokens[1..].iter().map(|t| t.text.to_string()).collect();
+        let definition = &self.macros[&name];
+        let expected = definition.params.len();
+        let mut bound: HashMap<String, String> = definition
+            .params
+            .iter()
+            .cloned()
+            .zip(arguments.iter().cloned())
+            .collect();
+        let body = definition.body.clone();
+        if arguments.len() != expected {
+            let kind = ExpandErrorKind::ArgumentCount {
+                name: tokens[0].text.to_string(),
+                expected,
+                found: arguments.len(),
+            };
+            return self.error(&here, kind);
+        }
+        self.expansions += 1;
+        bound.insert("@".to_string(), self.expansions.to_string());
+        if !code[..split].trim().is_empty() {
+            self.lines.push(code[..split].to_string());
+            self.origins.push(here.clone());
+        }
+        let call_site = here
+            .expanded_from
+            .clone()
+            .unwrap_or((here.file.clone(), here.line));
+        self.active.push(name);
+        for (line, mut there) in body {
+            there.expanded_from = Some(call_site.clone());
+            self.line(&line, there, &bound);
+        }
+        self.active.pop();
+    }
+}
+
+pub fn expand(
+    source: &str,
+    name: &str,
+    loader: &mut Loader,
+) -> Result<Expansion, Vec<ExpandError>> {
+    let mut expander = Expander {
+        loader,
+        constants: HashMap::new(),
+        macros: HashMap::new(),
+        included: HashSet::new(),
+        active: Vec::new(),
+        expansions: 0,
+        lines: Vec::new(),
+        origins: Vec::new(),
+        errors: Vec::new(),
+    };
+    expander.file(name, source);
+    if !expander.errors.is_empty() {
+        return Err(expander.errors);
+    }
+    let mut text = expander.lines.join("\n");
+    text.push('\n');
+    Ok(Expansion {
+        text,
+        origins: expander.origins,
+    })
+}
+
+// expand then parse, parse errors are pointed back at the lines that produced them
+pub fn assemble(
+    source: &str,
+    name: &str,
+    loader: &mut Loader,
+) -> Result<(Expansion, Program), Vec<ExpandError>> {
+    let expansion = expand(source, name, loader)?;
+    match parse_program(&expansion.text) {
+        Ok(program) => Ok((expansion, program)),
+        Err(errors) => Err(errors
+            .into_iter()
+            .map(|e| {
+                let origin = &expansion.origins[e.line - 1];
+                let (column, width) = if origin.verbatim {
+                    (e.column, e.width)
+                } else {
+                    origin.span
+                };
+                ExpandError {
+                    file: origin.file.clone(),
+                    line: origin.line,
+                    column,
+                    width,
+                    kind: ExpandErrorKind::Parse(e.kind),
+                    suggestion: e.suggestion,
+                    expanded_from: origin.expanded_from.clone(),
+                }
+            })
+            .collect()),
+    }
+}
+
+impl ExecutionPlane {
+    // the node keeps the expanded text as its source, that is what the game can load
+    pub fn load_node_expanded(
+        &mut self,
+        index: u8,
+        source: &str,
+        name: &str,
+        loader: &mut Loader,
+    ) -> Result<(), Vec<ExpandError>> {
+        self.check_programmable(index).map_err(|e| {
+            vec![ExpandError {
+                file: name.to_string(),
+                line: e.line,
+                column: e.column,
+                width: e.width,
+                kind: ExpandErrorKind::Parse(e.kind),
+                suggestion: None,
+                expanded_from: None,
+            }]
+        })?;
+        let (expansion, program) = assemble(source, name, loader)?;
+        self.install_program(index, program);
+        self.sources[index as usize] = Some(super::save::section_body(&expansion.text));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Dst, Instruction, Port, Register, Src, TruePort, INSTRUCTIONS_PER_NODE};
+
+    fn no_files(path: &str) -> io::Result<String> {
+        Err(io::Error::new(io::ErrorKind::NotFound, path.to_string()))
+    }
+
+    fn expand_text(source: &str) -> String {
+        expand(source, "main", &mut no_files).unwrap().text
+    }
+
+    fn first_error(source: &str) -> ExpandError {
+        assemble(source, "main", &mut no_files)
+            .unwrap_err()
+            .remove(0)
+    }
+
+    #[test]
+    fn constants_are_substituted() {
+        let source = ".const OUT RIGHT\n.const BIAS 5\n.const TWICE $bias\nADD $TWICE\nMOV ACC, $out # to $out\n";
+        assert_eq!("ADD 5\nMOV ACC, RIGHT # to $out\n", expand_text(source));
+    }
+
+    #[test]
+    fn macros_expand_with_arguments() {
+        let source = ".macro relay from, to\nMOV $from, ACC\nMOV ACC, $to\n.endm\nTOP: relay UP, DOWN\n!RELAY LEFT RIGHT\nJMP TOP";
+        assert_eq!(
+            "TOP:\nMOV UP, ACC\nMOV ACC, DOWN\n!\nMOV LEFT, ACC\nMOV ACC, RIGHT\nJMP TOP\n",
+            expand_text(source)
+        );
+        let (_, program) = assemble(source, "main", &mut no_files).unwrap();
+        assert_eq!(5, program.instructions.len());
+        assert_eq!(vec![2], program.breakpoints);
+        assert_eq!(
+            Instruction::Mov(
+                Src::Port(Port::True(TruePort::Left)),
+                Dst::Register(Register::Acc)
+            ),
+            program.instructions[2]
+        );
+    }
+
+    #[test]
+    fn macro_labels_are_unique_per_expansion() {
+        let source = ".macro wait n\nMOV $n, ACC\nW$@: SUB 1\nJGZ W$@\n.endm\nwait 3\nwait 5\n";
+        let (expansion, program) = assemble(source, "main", &mut no_files).unwrap();
+        assert!(expansion.text.contains("W1: SUB 1\nJGZ W1\n"));
+        assert!(expansion.text.contains("W2: SUB 1\nJGZ W2\n"));
+        assert_eq!(Instruction::Jgz(1), program.instructions[2]);
+        assert_eq!(Instruction::Jgz(4), program.instructions[5]);
+    }
+
+    #[test]
+    fn nested_macros() {
+        let source = ".macro twice x\nADD $x\nADD $x\n.endm\n.macro four x\ntwice $x\ntwice $x\n.endm\nfour 2";
+        assert_eq!("ADD 2\n".repeat(4), expand_text(source));
+
+        let err = first_error(".macro loop\nloop\n.endm\nloop");
+        assert_eq!(
+            ExpandErrorKind::RecursiveMacro("loop".to_string()),
+            err.kind
+        );
+        assert_eq!(2, err.line);
+        assert_eq!(Some(("main".to_string(), 4)), err.expanded_from);
+    }
+
+    #[test]
+    fn includes() {
+        let mut files = |path: &str| match path {
+            "lib.tis" => Ok(
+                ".const OUT DOWN\n.macro send v\nMOV $v, $OUT\n.endm\n.include \"main\""
+                    .to_string(),
+            ),
+            _ => no_files(path),
+        };
+        let source = ".include \"lib.tis\"\n.include lib.tis\nsend 1\n.include missing.tis";
+        let errors = expand(source, "main", &mut files).unwrap_err();
+        assert_eq!(1, errors.len());
+        assert_eq!(4, errors[0].line);
+        assert!(
+            matches!(&errors[0].kind, ExpandErrorKind::Include { path, .. } if path == "missing.tis")
+        );
+
+        let text = expand(&source[..source.len() - 21], "main", &mut files)
+            .unwrap()
+            .text;
+        assert_eq!("MOV 1, DOWN\n", text);
+    }
+
+    #[test]
+    fn expansion_errors() {
+        let err = first_error("ADD $NOPE");
+        assert_eq!(ExpandErrorKind::UndefinedName("NOPE".to_string()), err.kind);
+        assert_eq!((1, 5, 5), (err.line, err.column, err.width));
+
+        let err = first_error(".const VALUE 1\nADD $VALEU");
+        assert_eq!(Some("did you mean '$VALUE'?".to_string()), err.suggestion);
+
+        let err = first_error(".macro m a\nADD $a\n.endm\nm 1, 2");
+        assert_eq!(
+            ExpandErrorKind::ArgumentCount {
+                name: "m".to_string(),
+                expected: 1,
+                found: 2
+            },
+            err.kind
+        );
+
+        assert_eq!(
+            ExpandErrorKind::UnterminatedMacro("m".to_string()),
+            first_error(".macro m\nNOP").kind
+        );
+        assert_eq!(ExpandErrorKind::UnexpectedEndm, first_error(".endm").kind);
+        assert_eq!(
+            ExpandErrorKind::InvalidName("MOV".to_string()),
+            first_error(".macro MOV\n.endm").kind
+        );
+        assert_eq!(
+            ExpandErrorKind::DirectiveInMacro("const".to_string()),
+            first_error(".macro m\n.const X 1\n.endm").kind
+        );
+        assert_eq!(
+            ExpandErrorKind::UnknownDirective("define".to_string()),
+            first_error(".define X 1").kind
+        );
+        assert_eq!(
+            ExpandErrorKind::Usage(".const NAME VALUE"),
+            first_error(".const X").kind
+        );
+    }
+
+    #[test]
+    fn parse_errors_map_to_the_original_source() {
+        // verbatim line, the parser's own column survives
+        let err = first_error(".const X 1\n\nADD 1\n  FOO 2");
+        assert_eq!((4, 3, 3), (err.line, err.column, err.width));
+        assert_eq!(
+            ExpandErrorKind::Parse(ParseErrorKind::UnknownMnemonic("FOO".to_string())),
+            err.kind
+        );
+
+        // substituted, the whole line is blamed
+        let err = first_error(".const BIG 1000\nADD $BIG");
+        assert_eq!((2, 1, 8), (err.line, err.column, err.width));
+
+        // inside a macro, the body line plus where it was called from
+        let err = first_error(".macro m\nSAV\nMOV 1, 2\n.endm\nSWP\nm");
+        assert_eq!(3, err.line);
+        assert_eq!(Some(("main".to_string(), 6)), err.expanded_from);
+    }
+
+    #[test]
+    fn expanded_node_must_fit() {
+        let source = format!(".macro pad\n{}.endm\npad\npad\npad", "NOP\n".repeat(7));
+        assert!(assemble(&source, "main", &mut no_files).is_ok());
+        let err = first_error(&format!("{}\nNOP", source));
+        assert_eq!(
+            ExpandErrorKind::Parse(ParseErrorKind::TooManyInstructions(
+                INSTRUCTIONS_PER_NODE + 1
+            )),
+            err.kind
+        );
+        assert_eq!(13, err.line);
+    }
+
+    #[test]
+    fn load_expanded_node() {
+        let mut plane = ExecutionPlane::new();
+        plane
+            .load_node_expanded(0, ".const OUT DOWN\nMOV 1, $OUT", "main", &mut no_files)
+            .unwrap();
+        assert_eq!("@0\nMOV 1, DOWN\n\n", plane.to_save());
+    }
+}
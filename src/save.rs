@@ -0,0 +1,365 @@
+use std::fmt;
+
+use super::disasm::disassemble_node;
+use super::parse::{parse_program, ParseError, Program};
+use super::validate::Violation;
+use super::{ExecutionPlane, INSTRUCTIONS_PER_NODE, NODES_PER_PLANE};
+
+// the game's solution files, a '@N' header line per node followed by its source
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveErrorKind {
+    MissingHeader,
+    InvalidHeader(String),
+    NodeOutOfRange(usize),
+    DuplicateNode(usize),
+    // every error of every node, with lines counted from the top of the file
+    Parse(Vec<ParseError>),
+    Constraint(Violation),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveError {
+    // 1 indexed line in the save file
+    pub line: usize,
+    pub kind: SaveErrorKind,
+}
+
+impl fmt::Display for SaveErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingHeader => write!(f, "expected a '@N' node header"),
+            Self::InvalidHeader(h) => write!(f, "invalid node header '{}'", h),
+            Self::NodeOutOfRange(n) => write!(f, "node {} does not exist on this plane", n),
+            Self::DuplicateNode(n) => write!(f, "node {} appears more than once", n),
+            Self::Parse(errors) => {
+                write!(f, "{}", errors[0].kind)?;
+                if errors.len() > 1 {
+                    write!(f, " (and {} more)", errors.len() - 1)?;
+                }
+                Ok(())
+            }
+            Self::Constraint(v) => write!(f, "{}", v.kind),
+        }
+    }
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+// what the game writes after '@N' for a node holding `source`
+pub(crate) fn section_body(source: &str) -> String {
+    format!("\n{}\n\n", source.trim_end_matches(['\r', '\n']))
+}
+
+fn parse_header(line: &str) -> Option<Result<usize, String>> {
+    let header = line.trim_end();
+    let digits = header.strip_prefix('@')?;
+    Some(digits.parse().map_err(|_| header.to_string()))
+}
+
+pub(crate) struct Section<'a> {
+    pub node: usize,
+    // line of the '@N' header
+    pub line: usize,
+    // everything after '@N' up to the next header, newlines included
+    pub body: &'a str,
+}
+
+impl<'a> Section<'a> {
+    // the node's own text, without the rest of the header line or the blank separator
+    pub fn source(&self) -> &'a str {
+        let source = match self.body.find('\n') {
+            Some(i) => &self.body[i + 1..],
+            None => "",
+        };
+        source.trim_end_matches(['\r', '\n'])
+    }
+}
+
+pub(crate) fn split_sections(text: &str) -> Result<Vec<Section<'_>>, SaveError> {
+    // node, header line, where the header starts and where its body starts
+    let mut headers: Vec<(usize, usize, usize, usize)> = Vec::new();
+    let mut offset = 0;
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+        let error = |kind| SaveError { line: i + 1, kind };
+        match parse_header(line) {
+            Some(Ok(node)) => {
+                if node >= NODES_PER_PLANE {
+                    return Err(error(SaveErrorKind::NodeOutOfRange(node)));
+                }
+                if headers.iter().any(|h| h.0 == node) {
+                    return Err(error(SaveErrorKind::DuplicateNode(node)));
+                }
+                headers.push((node, i + 1, start, start + line.trim_end().len()));
+            }
+            Some(Err(header)) => return Err(error(SaveErrorKind::InvalidHeader(header))),
+            None if headers.is_empty() && !line.trim().is_empty() => {
+                return Err(error(SaveErrorKind::MissingHeader))
+            }
+            None => (),
+        }
+    }
+    let ends = headers
+        .iter()
+        .skip(1)
+        .map(|h| h.2)
+        .chain(std::iter::once(text.len()));
+    Ok(headers
+        .iter()
+        .zip(ends)
+        .map(|(&(node, line, _, body_start), end)| Section {
+            node,
+            line,
+            body: &text[body_start..end],
+        })
+        .collect())
+}
+
+impl ExecutionPlane {
+    pub fn from_save(text: &str) -> Result<Self, SaveError> {
+        let mut plane = Self::new();
+        plane.load_save(text)?;
+        Ok(plane)
+    }
+    // '@N' counts compute nodes only, so the same save means different positions per layout
+    pub(crate) fn save_node(&self, n: usize) -> Option<u8> {
+        self.compute_nodes().nth(n)
+    }
+    pub fn load_save(&mut self, text: &str) -> Result<(), SaveError> {
+        let sections = split_sections(text)?;
+        let mut nodes = Vec::with_capacity(sections.len());
+        for section in &sections {
+            let Some(node) = self.save_node(section.node) else {
+                return Err(SaveError {
+                    line: section.line,
+                    kind: SaveErrorKind::NodeOutOfRange(section.node),
+                });
+            };
+            nodes.push(node);
+        }
+        // nothing is installed until every section has parsed, a bad save changes nothing
+        let mut errors = Vec::new();
+        let mut programs = Vec::with_capacity(sections.len());
+        for (section, node) in sections.iter().zip(nodes) {
+            match parse_program(section.body) {
+                Ok(program) => programs.push((node, program, section.body)),
+                Err(e) => errors.extend(e.into_iter().map(|mut e| {
+                    e.node = Some(node);
+                    e.line += section.line - 1;
+                    e
+                })),
+            }
+        }
+        if let Some(first) = errors.first() {
+            return Err(SaveError {
+                line: first.line,
+                kind: SaveErrorKind::Parse(errors),
+            });
+        }
+        for (node, program, body) in programs {
+            self.install_program(node, program);
+            self.sources[node as usize] = Some(body.to_string());
+        }
+        Ok(())
+    }
+    // the loaded text without its header line, as long as it is still what the node runs
+    pub(crate) fn live_source(&self, node: usize) -> Option<(&str, Program)> {
+        let body = self.sources[node].as_deref()?;
+        let text = body.split_once('\n').map_or("", |(_, text)| text);
+        let parsed = parse_program(text).ok()?;
+        (parsed.instructions == self.program(node)).then_some((text, parsed))
+    }
+    pub fn to_save(&self) -> String {
+        let mut text = String::new();
+        for (i, node) in self.compute_nodes().enumerate() {
+            let node = node as usize;
+            // code put in by hand or from bytecode has no text of its own, so make some
+            let body = match (&self.sources[node], self.live_source(node)) {
+                (Some(body), Some(_)) => body.clone(),
+                (None, _) if self.program(node).is_empty() => continue,
+                _ => {
+                    let start = node * INSTRUCTIONS_PER_NODE;
+                    let slots = &self.instructions[start..start + INSTRUCTIONS_PER_NODE];
+                    section_body(&disassemble_node(slots))
+                }
+            };
+            text.push('@');
+            text.push_str(&i.to_string());
+            text.push_str(&body);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Instruction, NodeKind, Plane, Src};
+
+    const SOLUTION: &str = "@0\nMOV UP, DOWN\n\n@1\n\n\n@2\nSTART:\nMOV LEFT, ACC # c\nJGZ START\n\n@3\n\n\n@4\nMOV UP, RIGHT\n\n@5\nMOV LEFT, DOWN\n\n@6\n\n\n@7\n\n\n@8\n\n\n@9\nMOV UP, DOWN\n\n@10\n\n\n@11\n\n";
+
+    #[test]
+    fn save_round_trip() {
+        let plane = ExecutionPlane::from_save(SOLUTION).unwrap();
+        assert_eq!(SOLUTION, plane.to_save());
+        assert!(plane.sources.iter().all(|s| s.is_some()));
+    }
+
+    #[test]
+    fn save_round_trip_keeps_line_endings() {
+        let text = "@0\r\nADD 1\r\nsub 1  \r\n\r\n@1\r\n\r\n";
+        let plane = ExecutionPlane::from_save(text).unwrap();
+        assert_eq!(text, plane.to_save());
+    }
+
+    #[test]
+    fn save_loads_programs() {
+        let mut plane = ExecutionPlane::from_save(SOLUTION).unwrap();
+        plane.step();
+        assert_eq!(2, plane.nodes[2].instruction_len);
+        assert_eq!(0, plane.nodes[1].instruction_len);
+        assert!(matches!(
+            plane.get_node_instructions_mut(2)[1],
+            Some(Instruction::Jgz(0))
+        ));
+    }
+
+    #[test]
+    fn load_node_ends_up_in_save() {
+        let mut plane = ExecutionPlane::new();
+        plane.load_node(3, "ADD 1\nNEG\n").unwrap();
+        plane.load_node(0, "").unwrap();
+        assert_eq!("@0\n\n\n@3\nADD 1\nNEG\n\n", plane.to_save());
+        let reloaded = ExecutionPlane::from_save(&plane.to_save()).unwrap();
+        assert_eq!(plane.to_save(), reloaded.to_save());
+    }
+
+    #[test]
+    fn save_partial_sections() {
+        let mut plane = ExecutionPlane::from_save("@7\nADD 5").unwrap();
+        assert_eq!("@7\nADD 5", plane.to_save());
+        plane.step();
+        assert!(matches!(
+            plane.nodes[7].current_instruction,
+            Some(Instruction::Add(Src::Literal(5)))
+        ));
+    }
+
+    #[test]
+    fn save_errors() {
+        let err = ExecutionPlane::from_save("ADD 1\n@0\n").err().unwrap();
+        assert_eq!(SaveErrorKind::MissingHeader, err.kind);
+
+        let err = ExecutionPlane::from_save("@0\n\n@x\n").err().unwrap();
+        assert_eq!(3, err.line);
+        assert_eq!(SaveErrorKind::InvalidHeader("@x".to_string()), err.kind);
+
+        let err = ExecutionPlane::from_save("@12\n").err().unwrap();
+        assert_eq!(SaveErrorKind::NodeOutOfRange(12), err.kind);
+
+        let err = ExecutionPlane::from_save("@1\n@1\n").err().unwrap();
+        assert_eq!(SaveErrorKind::DuplicateNode(1), err.kind);
+
+        let err = ExecutionPlane::from_save("@0\nNOP\n\n@1\nNOP\nBAD\n")
+            .err()
+            .unwrap();
+        assert_eq!(6, err.line);
+        assert!(matches!(err.kind, SaveErrorKind::Parse(_)));
+    }
+
+    #[test]
+    fn save_follows_the_live_program() {
+        let mut plane = ExecutionPlane::new();
+        plane.load_node(0, "ADD 1\nNEG").unwrap();
+        plane.get_node_instructions_mut(0)[0] = Some(Instruction::Hcf);
+        assert_eq!("@0\nHCF\nNEG\n\n", plane.to_save());
+
+        let mut plane = ExecutionPlane::new();
+        let slots = plane.get_node_instructions_mut(6);
+        slots[0] = Some(Instruction::Add(Src::Literal(2)));
+        slots[1] = Some(Instruction::Jgz(0));
+        let mut reloaded = ExecutionPlane::from_save(&plane.to_save()).unwrap();
+        assert_eq!(
+            plane.get_node_instructions_mut(6),
+            reloaded.get_node_instructions_mut(6)
+        );
+    }
+
+    #[test]
+    fn save_from_bytecode() {
+        let mut plane = ExecutionPlane::from_save(SOLUTION).unwrap();
+        let mut decoded = ExecutionPlane::from_bytecode(&plane.to_bytecode().unwrap()).unwrap();
+        let mut reloaded = ExecutionPlane::from_save(&decoded.to_save()).unwrap();
+        for i in 0..NODES_PER_PLANE as u8 {
+            let expected = plane.get_node_instructions_mut(i).to_vec();
+            assert_eq!(expected, decoded.get_node_instructions_mut(i));
+            assert_eq!(expected, reloaded.get_node_instructions_mut(i));
+        }
+    }
+
+    #[test]
+    fn save_that_fails_changes_nothing() {
+        let mut plane = ExecutionPlane::from_save(SOLUTION).unwrap();
+        let before: Vec<_> = (0..NODES_PER_PLANE as u8)
+            .map(|i| plane.get_node_instructions_mut(i).to_vec())
+            .collect();
+        let err = plane.load_save("@0\nADD 1\n\n@2\nBAD\n").err().unwrap();
+        assert!(matches!(err.kind, SaveErrorKind::Parse(_)));
+        assert_eq!(SOLUTION, plane.to_save());
+        for (i, program) in before.iter().enumerate() {
+            assert_eq!(program, plane.get_node_instructions_mut(i as u8));
+        }
+    }
+
+    #[test]
+    fn save_reports_errors_of_every_node() {
+        let text = "@0\nNOP\nBAD\n\n@1\n\n\n@2\nMOV 1, 2\n\n";
+        let err = ExecutionPlane::from_save(text).err().unwrap();
+        let SaveErrorKind::Parse(errors) = err.kind else {
+            panic!("expected parse errors");
+        };
+        let locations: Vec<_> = errors.iter().map(|e| (e.node, e.line)).collect();
+        assert_eq!(vec![(Some(0), 3), (Some(2), 9)], locations);
+        let report = crate::parse::render_report(text, &errors);
+        assert!(report.contains("9 | MOV 1, 2\n"));
+    }
+
+    #[test]
+    fn save_numbers_compute_nodes_only() {
+        let mut layout = [NodeKind::Compute; NODES_PER_PLANE];
+        layout[1] = NodeKind::Stack;
+        layout[4] = NodeKind::Damaged;
+        let mut plane = ExecutionPlane::with_layout(layout);
+        assert_eq!(Some(5), plane.save_node(3));
+        assert_eq!(None, plane.save_node(10));
+        let text = "@0\nMOV 1, RIGHT\n\n@1\nMOV LEFT, ACC\n\n";
+        plane.load_save(text).unwrap();
+        assert_eq!(
+            1,
+            plane.get_node_instructions_mut(2).iter().flatten().count()
+        );
+        assert_eq!(text, plane.to_save());
+        assert!(plane
+            .disassemble()
+            .starts_with("@0\nMOV 1, RIGHT\n\n@1\nMOV LEFT, ACC\n"));
+        assert!(plane.disassemble().ends_with("@9\n\n"));
+
+        let err = plane.load_save("@10\n").err().unwrap();
+        assert_eq!(SaveErrorKind::NodeOutOfRange(10), err.kind);
+        // errors name the grid node, the same one load_node would
+        let err = plane.load_save("@1\nBAD\n").err().unwrap();
+        let SaveErrorKind::Parse(errors) = err.kind else {
+            panic!("expected parse errors");
+        };
+        assert_eq!(Some(2), errors[0].node);
+        assert_eq!(Some(2), plane.load_node(2, "BAD").err().unwrap()[0].node);
+    }
+}
@@ -0,0 +1,55 @@
+use super::{
+    map_port, reverse_map_node, withdraw, ExecutionPlane, Mode, NodeKind, PortValue, TruePort,
+    ANY_PRIORITY, STACK_CAPACITY,
+};
+
+// T30 stack nodes, they run after every compute node so they see the same port timing
+// a reader or writer would: pushes are taken a cycle after the write, pops offered the
+// cycle before the read
+
+impl ExecutionPlane {
+    fn neighbours(&self, stack: usize) -> impl Iterator<Item = (TruePort, usize, usize)> + '_ {
+        // stacks do not talk to each other
+        ANY_PRIORITY.iter().filter_map(move |&d| {
+            let neighbour = reverse_map_node(d, stack)? as usize;
+            (self.layout[neighbour] == NodeKind::Compute)
+                .then(|| (d, neighbour, map_port(d, stack)))
+        })
+    }
+    pub(crate) fn stack_step(&mut self, stack: usize) {
+        let writer = stack as u8;
+        let neighbours: Vec<_> = self.neighbours(stack).collect();
+        for &(d, _, index) in &neighbours {
+            if self.stacks[stack].len() >= STACK_CAPACITY {
+                break;
+            }
+            let Some(write) = self.ports[index].filter(|w| w.writer != writer) else {
+                continue;
+            };
+            withdraw(&mut self.ports, write.writer);
+            self.stacks[stack].push(write.value);
+            self.clear_writes.push((write.writer, d.reverse()));
+            // whatever we were offering is no longer the top
+            withdraw(&mut self.ports, writer);
+        }
+        for &(d, neighbour, index) in &neighbours {
+            let node = &self.nodes[neighbour];
+            // a neighbour stuck writing to us needs the port more than our offer does
+            let blocked = node.mode == Mode::Write
+                && node.port_write_buffer.is_some()
+                && matches!(node.direction, Some(p) if p == d.reverse() || p == TruePort::Any);
+            if blocked {
+                if self.ports[index].is_some_and(|p| p.writer == writer) {
+                    self.ports[index] = None;
+                }
+                continue;
+            }
+            let Some(&value) = self.stacks[stack].last() else {
+                continue;
+            };
+            if self.ports[index].is_none() && self.queued_writes[index].is_none() {
+                self.queued_writes[index] = Some(PortValue { value, writer });
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use std::ops::Range;
+
+use super::console::Console;
+use super::{withdraw, ExecutionPlane, PortValue, TruePort, NODES_PER_PLANE};
+
+// test data for the ports that lead off the grid, input streams sit above the top row
+// and output sinks below the bottom one
+
+// the up ports of nodes 0 to 3
+pub const INPUT_PORTS: Range<usize> = 0..4;
+// the down ports of nodes 8 to 11
+pub const OUTPUT_PORTS: Range<usize> = 27..31;
+
+pub(crate) struct Input {
+    values: Box<dyn Iterator<Item = i16>>,
+}
+
+// inputs need a writer id of their own so reads withdraw their offer like any other
+pub(crate) fn input_writer(port: usize) -> u8 {
+    (NODES_PER_PLANE + port) as u8
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Received {
+    pub value: i16,
+    // the cycle the sink took it on
+    pub cycle: u64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Mismatch {
+    // position in the output sequence
+    pub index: usize,
+    // None when more values arrived than were expected
+    pub expected: Option<i16>,
+    pub found: i16,
+    pub cycle: u64,
+}
+
+// what hangs off a bottom port
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Sink {
+    Values(Output),
+    Console(Console),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Output {
+    received: Vec<Received>,
+    expected: Option<Vec<i16>>,
+    mismatch: Option<Mismatch>,
+}
+
+impl Output {
+    // records everything, never complete
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn expecting(expected: Vec<i16>) -> Self {
+        Self {
+            expected: Some(expected),
+            ..Self::default()
+        }
+    }
+    pub fn received(&self) -> &[Received] {
+        &self.received
+    }
+    pub fn values(&self) -> Vec<i16> {
+        self.received.iter().map(|r| r.value).collect()
+    }
+    pub fn expected(&self) -> Option<&[i16]> {
+        self.expected.as_deref()
+    }
+    // the first value that differs, later ones are not checked
+    pub fn mismatch(&self) -> Option<Mismatch> {
+        self.mismatch
+    }
+    pub fn complete(&self) -> bool {
+        self.expected
+            .as_ref()
+            .is_some_and(|e| self.received.len() >= e.len())
+    }
+    pub fn passed(&self) -> bool {
+        self.complete() && self.mismatch.is_none()
+    }
+    fn receive(&mut self, value: i16, cycle: u64) {
+        let index = self.received.len();
+        self.received.push(Received { value, cycle });
+        let Some(expected) = &self.expected else {
+            return;
+        };
+        let expected = expected.get(index).copied();
+        if self.mismatch.is_none() && expected != Some(value) {
+            self.mismatch = Some(Mismatch {
+                index,
+                expected,
+                found: value,
+                cycle,
+            });
+        }
+    }
+}
+
+impl ExecutionPlane {
+    // one value per read, a stream that runs dry blocks its reader from then on
+    pub fn attach_input<I>(&mut self, port: usize, values: I)
+    where
+        I: IntoIterator<Item = i16>,
+        I::IntoIter: 'static,
+    {
+        if !INPUT_PORTS.contains(&port) {
+            panic!(
+                "inputs go on the top edge, port {} is not one of 0 to 3",
+                port
+            );
+        }
+        // whatever the old stream had on offer goes with it
+        withdraw(&mut self.ports, input_writer(port));
+        self.queued_writes[port] = None;
+        self.inputs[port] = Some(Input {
+            values: Box::new(values.into_iter()),
+        });
+    }
+    pub fn detach_input(&mut self, port: usize) {
+        if self.inputs.get_mut(port).and_then(Option::take).is_some() {
+            withdraw(&mut self.ports, input_writer(port));
+            self.queued_writes[port] = None;
+        }
+    }
+    fn sink_slot(&mut self, port: usize) -> Option<&mut Option<Sink>> {
+        self.outputs.get_mut(port.checked_sub(OUTPUT_PORTS.start)?)
+    }
+    fn attach_sink(&mut self, port: usize, sink: Sink) {
+        let Some(slot) = self.sink_slot(port) else {
+            panic!(
+                "outputs go on the bottom edge, port {} is not one of 27 to 30",
+                port
+            );
+        };
+        *slot = Some(sink);
+    }
+    pub(crate) fn sink(&self, port: usize) -> Option<&Sink> {
+        self.outputs
+            .get(port.checked_sub(OUTPUT_PORTS.start)?)?
+            .as_ref()
+    }
+    pub fn attach_output(&mut self, port: usize, output: Output) {
+        self.attach_sink(port, Sink::Values(output));
+    }
+    pub fn attach_console(&mut self, port: usize, console: Console) {
+        self.attach_sink(port, Sink::Console(console));
+    }
+    pub fn detach_output(&mut self, port: usize) -> Option<Output> {
+        let slot = self.sink_slot(port)?;
+        match slot.take() {
+            Some(Sink::Values(output)) => Some(output),
+            other => {
+                *slot = other;
+                None
+            }
+        }
+    }
+    pub fn detach_console(&mut self, port: usize) -> Option<Console> {
+        let slot = self.sink_slot(port)?;
+        match slot.take() {
+            Some(Sink::Console(console)) => Some(console),
+            other => {
+                *slot = other;
+                None
+            }
+        }
+    }
+    pub fn output(&self, port: usize) -> Option<&Output> {
+        match self.sink(port)? {
+            Sink::Values(output) => Some(output),
+            _ => None,
+        }
+    }
+    pub fn console(&self, port: usize) -> Option<&Console> {
+        match self.sink(port)? {
+            Sink::Console(console) => Some(console),
+            _ => None,
+        }
+    }
+    // false without any expectations to meet
+    pub fn outputs_complete(&self) -> bool {
+        let mut expecting = self
+            .outputs
+            .iter()
+            .flatten()
+            .filter_map(|sink| match sink {
+                Sink::Values(output) if output.expected.is_some() => Some(output),
+                _ => None,
+            })
+            .peekable();
+        expecting.peek().is_some() && expecting.all(Output::complete)
+    }
+    pub(crate) fn output_step(&mut self) {
+        // sinks read like a node below the grid would, a cycle after the write
+        for (port, sink) in OUTPUT_PORTS.zip(self.outputs.iter_mut()) {
+            let Some(sink) = sink else {
+                continue;
+            };
+            let Some(write) = self.ports[port].take() else {
+                continue;
+            };
+            withdraw(&mut self.ports, write.writer);
+            match sink {
+                Sink::Values(output) => output.receive(write.value, self.cycle),
+                Sink::Console(console) => console.receive(write.value),
+            }
+            self.clear_writes.push((write.writer, TruePort::Down));
+        }
+    }
+    pub(crate) fn input_step(&mut self) {
+        for port in INPUT_PORTS {
+            let Some(input) = &mut self.inputs[port] else {
+                continue;
+            };
+            if self.ports[port].is_some() || self.queued_writes[port].is_some() {
+                continue;
+            }
+            if let Some(value) = input.values.next() {
+                self.queued_writes[port] = Some(PortValue {
+                    value,
+                    writer: input_writer(port),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Mode, Plane};
+
+    #[test]
+    fn input_feeds_one_value_per_read() {
+        let mut plane = ExecutionPlane::new();
+        plane.attach_input(1, vec![3, 4, 5]);
+        plane.load_node(1, "ADD UP").unwrap();
+        let mut seen = vec![];
+        for _ in 0..8 {
+            plane.step();
+            seen.push(plane.nodes[1].acc);
+        }
+        assert_eq!(vec![0, 3, 7, 12, 12, 12, 12, 12], seen);
+        // a finished stream blocks rather than handing out zeroes
+        assert_eq!(Mode::Read, plane.nodes[1].mode);
+    }
+
+    #[test]
+    fn input_from_an_iterator() {
+        let mut plane = ExecutionPlane::new();
+        plane.attach_input(3, (1..).map(|i| i * 2));
+        plane.load_node(3, "MOV UP, ACC\nMOV ACC, LEFT").unwrap();
+        plane.load_node(2, "ADD RIGHT").unwrap();
+        for _ in 0..20 {
+            plane.step();
+        }
+        assert!(plane.nodes[2].acc > 20);
+        assert_eq!(0, plane.nodes[2].acc % 2);
+    }
+
+    #[test]
+    fn input_reattached_drops_the_old_offer() {
+        let mut plane = ExecutionPlane::new();
+        plane.attach_input(0, vec![9]);
+        plane.step();
+        plane.attach_input(0, vec![1]);
+        plane.load_node(0, "MOV UP, ACC").unwrap();
+        plane.step();
+        plane.step();
+        assert_eq!(1, plane.nodes[0].acc);
+        plane.detach_input(0);
+        assert!(plane.ports[0].is_none());
+    }
+
+    #[test]
+    fn output_records_values_and_cycles() {
+        let mut plane = ExecutionPlane::new();
+        plane.attach_output(28, Output::new());
+        plane
+            .load_node(9, "MOV 5, DOWN\nMOV 6, DOWN\nL: JMP L")
+            .unwrap();
+        for _ in 0..6 {
+            plane.step();
+        }
+        let output = plane.output(28).unwrap();
+        assert_eq!(vec![5, 6], output.values());
+        assert_eq!(2, output.received()[0].cycle);
+        assert_eq!(4, output.received()[1].cycle);
+        assert!(!output.complete());
+        assert!(!plane.outputs_complete());
+    }
+
+    #[test]
+    fn output_checks_against_expected() {
+        let mut plane = ExecutionPlane::new();
+        plane.attach_input(0, vec![1, 2, 3, 4]);
+        plane
+            .load_node(0, "MOV UP, ACC\nSUB 3\nJEZ X\nADD 3\nX: MOV ACC, DOWN")
+            .unwrap();
+        plane.load_node(4, "MOV UP, DOWN").unwrap();
+        plane.load_node(8, "MOV UP, DOWN").unwrap();
+        plane.attach_output(27, Output::expecting(vec![1, 2, 3, 4]));
+        for _ in 0..100 {
+            plane.step();
+            if plane.outputs_complete() {
+                break;
+            }
+        }
+        assert!(plane.outputs_complete());
+        let output = plane.detach_output(27).unwrap();
+        assert!(!output.passed());
+        let mismatch = output.mismatch().unwrap();
+        assert_eq!(
+            (2, Some(3), 0),
+            (mismatch.index, mismatch.expected, mismatch.found)
+        );
+        assert_eq!(output.received()[2].cycle, mismatch.cycle);
+        assert_eq!(None, plane.output(27));
+    }
+
+    #[test]
+    fn output_flags_extra_values() {
+        let mut output = Output::expecting(vec![7]);
+        output.receive(7, 1);
+        assert!(output.passed());
+        output.receive(7, 2);
+        assert_eq!(None, output.mismatch().unwrap().expected);
+    }
+
+    #[test]
+    #[should_panic]
+    fn output_only_on_the_bottom_edge() {
+        ExecutionPlane::new().attach_output(26, Output::new());
+    }
+
+    #[test]
+    #[should_panic]
+    fn input_only_on_the_top_edge() {
+        ExecutionPlane::new().attach_input(4, vec![1]);
+    }
+}
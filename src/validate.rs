@@ -0,0 +1,193 @@
+use std::fmt;
+
+use super::save::{split_sections, SaveError, SaveErrorKind};
+use super::ExecutionPlane;
+
+// what fits into a T21 in the game, we are more generous than that internally
+pub const LINES_PER_NODE: usize = 15;
+pub const CHARS_PER_LINE: usize = 18;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ViolationKind {
+    TooManyLines(usize),
+    LineTooLong(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Violation {
+    // grid index, like ParseError::node
+    pub node: u8,
+    // both 1 indexed and relative to the node's own source
+    pub line: usize,
+    pub column: usize,
+    pub kind: ViolationKind,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Strictness {
+    // any violation fails the load
+    Strict,
+    // violations are handed back as warnings
+    Lax,
+}
+
+impl fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooManyLines(n) => write!(
+                f,
+                "{} lines do not fit in a node ({} max)",
+                n, LINES_PER_NODE
+            ),
+            Self::LineTooLong(n) => {
+                write!(f, "line is {} characters long ({} max)", n, CHARS_PER_LINE)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "node {}, line {}, column {}: {}",
+            self.node, self.line, self.column, self.kind
+        )
+    }
+}
+
+pub fn validate_node(node: u8, source: &str) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    // trailing blank lines are just the gap before the next node
+    let lines: Vec<&str> = source.trim_end_matches(['\r', '\n']).lines().collect();
+    for (i, line) in lines.iter().enumerate() {
+        // the '!' of a breakpoint is typed into the line, so it counts too
+        let length = line.chars().count();
+        if length > CHARS_PER_LINE {
+            violations.push(Violation {
+                node,
+                line: i + 1,
+                column: CHARS_PER_LINE + 1,
+                kind: ViolationKind::LineTooLong(length),
+            });
+        }
+    }
+    if lines.len() > LINES_PER_NODE {
+        violations.push(Violation {
+            node,
+            line: LINES_PER_NODE + 1,
+            column: 1,
+            kind: ViolationKind::TooManyLines(lines.len()),
+        });
+    }
+    violations
+}
+
+impl ExecutionPlane {
+    pub fn from_save_validated(
+        text: &str,
+        strictness: Strictness,
+    ) -> Result<(Self, Vec<Violation>), SaveError> {
+        let mut plane = Self::new();
+        let violations = plane.load_save_validated(text, strictness)?;
+        Ok((plane, violations))
+    }
+    pub fn load_save_validated(
+        &mut self,
+        text: &str,
+        strictness: Strictness,
+    ) -> Result<Vec<Violation>, SaveError> {
+        let mut violations = Vec::new();
+        for section in split_sections(text)? {
+            let Some(node) = self.save_node(section.node) else {
+                return Err(SaveError {
+                    line: section.line,
+                    kind: SaveErrorKind::NodeOutOfRange(section.node),
+                });
+            };
+            violations.extend(validate_node(node, section.source()));
+            if strictness == Strictness::Strict {
+                if let Some(violation) = violations.first() {
+                    return Err(SaveError {
+                        line: section.line + violation.line,
+                        kind: SaveErrorKind::Constraint(*violation),
+                    });
+                }
+            }
+        }
+        self.load_save(text)?;
+        Ok(violations)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validate_accepts_game_sized_nodes() {
+        let source = "MOV RIGHT, ACC # c\n".repeat(LINES_PER_NODE);
+        assert_eq!(18, "MOV RIGHT, ACC # c".len());
+        assert!(validate_node(0, &source).is_empty());
+        assert!(validate_node(0, &(source + "\n\n")).is_empty());
+    }
+
+    #[test]
+    fn validate_reports_violations() {
+        let source = format!("NOP\n!MOV RIGHT, ACC # c\n{}", "NOP\n".repeat(14));
+        assert_eq!(
+            vec![
+                Violation {
+                    node: 3,
+                    line: 2,
+                    column: 19,
+                    kind: ViolationKind::LineTooLong(19),
+                },
+                Violation {
+                    node: 3,
+                    line: 16,
+                    column: 1,
+                    kind: ViolationKind::TooManyLines(16),
+                },
+            ],
+            validate_node(3, &source)
+        );
+    }
+
+    #[test]
+    fn strict_rejects_lax_warns() {
+        let text = "@0\nNOP\n\n@1\nMOV LEFT, RIGHT # too long\n\n";
+        let (plane, warnings) = ExecutionPlane::from_save_validated(text, Strictness::Lax).unwrap();
+        assert_eq!(1, warnings.len());
+        assert_eq!(1, warnings[0].node);
+        assert_eq!(text, plane.to_save());
+
+        let err = ExecutionPlane::from_save_validated(text, Strictness::Strict)
+            .err()
+            .unwrap();
+        assert_eq!(5, err.line);
+        assert!(matches!(
+            err.kind,
+            SaveErrorKind::Constraint(Violation {
+                node: 1,
+                line: 1,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn violations_name_the_grid_node() {
+        let mut layout = [crate::NodeKind::Compute; crate::NODES_PER_PLANE];
+        layout[0] = crate::NodeKind::Stack;
+        let mut plane = ExecutionPlane::with_layout(layout);
+        let text = "@0\nMOV LEFT, RIGHT # too long\n";
+        let warnings = plane.load_save_validated(text, Strictness::Lax).unwrap();
+        assert_eq!(1, warnings[0].node);
+        let err = plane
+            .load_save_validated("@11\n", Strictness::Lax)
+            .err()
+            .unwrap();
+        assert_eq!(SaveErrorKind::NodeOutOfRange(11), err.kind);
+    }
+}